@@ -1,33 +1,385 @@
-//! This crate exports a function that calculates the hash of the asset directory.
+//! This crate exports functions that calculate the hash of the mounted assets,
+//! and a [Manifest] listing the hash of every asset, which can be diffed to
+//! find out which files differ between two installs.
 //!
 //! ## Safety
 //! This crate has not been proven cryptographically secure.
 
+use serde::{Deserialize, Serialize};
 use sha256::digest;
-use std::{fs::File, io::Read, path::Path};
+use std::{
+    collections::{BTreeSet, HashMap},
+    io,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
 
-/// Return the sha256 hash for the asset directory.
+/// Files which may differ from the server's, and are not part of the asset hash:
+/// downloaded files, maps (which are sent by the server) and the server's map rotation.
+const EXCLUDED: &[&str] = &["cache", "maps", "map_rotation.txt"];
+/// Where the hashes of the last run are stored, to skip unchanged files.
+const HASH_CACHE: &str = "cache/integrity.json";
+
+/// Return the sha256 hash for the archives and directories mounted in [vfs::get].
+/// Downloaded files, maps and the map rotation are not included.
 /// ## Safety
 /// This crate has not been proven cryptographically secure.
+/// ## Errors
+/// Returns an error if the asset directory can't be read.
 #[allow(clippy::unnecessary_safety_doc)]
-pub fn get_asset_hash() -> String {
-    recurse("assets").unwrap()
+pub fn get_asset_hash() -> io::Result<String> {
+    Ok(get_asset_manifest()?.hash())
+}
+
+/// Return the [Manifest] of the archives and directories mounted in [vfs::get].
+/// Downloaded files, maps and the map rotation are not included.
+/// ## Errors
+/// Returns an error if the asset directory can't be read.
+pub fn get_asset_manifest() -> io::Result<Manifest> {
+    let vfs = vfs::get();
+    let roots = vfs.roots();
+    let exclude = roots
+        .iter()
+        .flat_map(|r| EXCLUDED.iter().map(|e| r.join(e)))
+        .collect::<Vec<_>>();
+    let cache = vfs.disk_path(HASH_CACHE).unwrap_or(HASH_CACHE.into());
+    Manifest::build(&roots, &exclude, &cache)
+}
+
+/// Return the sha256 hash of a single file.
+/// ## Errors
+/// Returns an error if the file can't be read.
+pub fn hash_file<P: AsRef<Path>>(p: P) -> io::Result<String> {
+    Ok(hash_bytes(&std::fs::read(p)?))
+}
+
+/// Return the sha256 hash of some data.
+pub fn hash_bytes(data: &[u8]) -> String {
+    digest(data)
+}
+
+/// A single file in a [Manifest].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// The path relative to its root, using `/` as separator.
+    /// Roots which are files are listed by their file name.
+    pub path: String,
+    /// The size in bytes.
+    pub size: u64,
+    /// The sha256 hash of the content.
+    pub sha256: String,
+}
+
+/// Every file in a directory, sorted by path.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// The files, sorted by path.
+    pub files: Vec<ManifestEntry>,
+}
+
+/// The files which differ between two manifests.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ManifestDiff {
+    /// Files only in the other manifest.
+    pub missing: Vec<String>,
+    /// Files only in this manifest.
+    pub extra: Vec<String>,
+    /// Files in both manifests, with different content.
+    pub changed: Vec<String>,
+}
+impl ManifestDiff {
+    /// Returns true if the manifests are the same.
+    pub fn is_empty(&self) -> bool {
+        self.missing.is_empty() && self.extra.is_empty() && self.changed.is_empty()
+    }
+}
+impl std::fmt::Display for ManifestDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for path in &self.missing {
+            writeln!(f, "missing: {path}")?;
+        }
+        for path in &self.extra {
+            writeln!(f, "extra: {path}")?;
+        }
+        for path in &self.changed {
+            writeln!(f, "changed: {path}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A previously calculated hash, reused while the file is unchanged.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedHash {
+    modified: u128,
+    size: u64,
+    sha256: String,
 }
 
-fn recurse<P: AsRef<Path>>(p: P) -> std::io::Result<String> {
-    let p = p.as_ref();
-    let mut total = String::new();
+impl Manifest {
+    /// Hashes every file in the `roots`, except the ones in `exclude`.
+    /// Missing roots are skipped.
+    /// Files with the same size and modification time as in `cache` are not read again,
+    /// and the cache is updated afterwards.
+    /// ## Errors
+    /// Returns an error if a file can't be read.
+    pub fn build<P: AsRef<Path>>(roots: &[P], exclude: &[P], cache: &Path) -> io::Result<Self> {
+        let exclude = exclude
+            .iter()
+            .map(|p| p.as_ref().to_path_buf())
+            .collect::<Vec<_>>();
+        let mut files = Vec::new();
+        for root in roots.iter().map(AsRef::as_ref).filter(|r| r.exists()) {
+            list_files(root, root, &exclude, &mut files)?;
+        }
+
+        let cached: HashMap<String, CachedHash> = std::fs::read(cache)
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default();
+
+        let mut entries = Vec::new();
+        let mut stale = Vec::new();
+        for (path, file) in files {
+            let metadata = std::fs::metadata(&file)?;
+            let modified = metadata
+                .modified()?
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos())
+                .unwrap_or_default();
+            match cached.get(&path) {
+                Some(c) if c.modified == modified && c.size == metadata.len() => {
+                    entries.push((path, c.clone()))
+                }
+                _ => stale.push((path, file, modified, metadata.len())),
+            }
+        }
+
+        for result in hash_parallel(stale) {
+            entries.push(result?);
+        }
+        entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        // The cache only saves time, so failing to write it is fine
+        if let Ok(data) = serde_json::to_vec(&entries.iter().cloned().collect::<HashMap<_, _>>()) {
+            if let Some(parent) = cache.parent() {
+                let _ = std::fs::create_dir_all(parent);
+            }
+            let _ = std::fs::write(cache, data);
+        }
+
+        Ok(Self {
+            files: entries
+                .into_iter()
+                .map(|(path, c)| ManifestEntry {
+                    path,
+                    size: c.size,
+                    sha256: c.sha256,
+                })
+                .collect(),
+        })
+    }
+
+    /// Return the sha256 hash of the whole manifest.
+    pub fn hash(&self) -> String {
+        let mut total = String::new();
+        for file in &self.files {
+            total.push_str(&format!("{} {} {}\n", file.path, file.size, file.sha256));
+        }
+        digest(total)
+    }
+
+    /// Return the files which differ between `self` and `other`.
+    pub fn diff(&self, other: &Self) -> ManifestDiff {
+        let ours = self
+            .files
+            .iter()
+            .map(|f| (&f.path, f))
+            .collect::<HashMap<_, _>>();
+        let theirs = other
+            .files
+            .iter()
+            .map(|f| (&f.path, f))
+            .collect::<HashMap<_, _>>();
+
+        let mut diff = ManifestDiff::default();
+        let paths = ours.keys().chain(theirs.keys()).collect::<BTreeSet<_>>();
+        for path in paths {
+            match (ours.get(path), theirs.get(path)) {
+                (Some(a), Some(b)) if a != b => diff.changed.push(path.to_string()),
+                (Some(_), None) => diff.extra.push(path.to_string()),
+                (None, Some(_)) => diff.missing.push(path.to_string()),
+                _ => {}
+            }
+        }
+        diff
+    }
+}
+
+fn list_files(
+    root: &Path,
+    p: &Path,
+    exclude: &[PathBuf],
+    files: &mut Vec<(String, PathBuf)>,
+) -> io::Result<()> {
+    if exclude.iter().any(|e| e == p) {
+        return Ok(());
+    }
     if p.is_dir() {
-        for p in p.read_dir()? {
-            let p = p?.path();
-            total.push_str(&recurse(p)?);
+        for entry in p.read_dir()? {
+            list_files(root, &entry?.path(), exclude, files)?;
         }
     } else {
-        let mut f = File::open(p)?;
-        let mut v = Vec::new();
-        f.read_to_end(&mut v)?;
-        total.push_str(&digest(v));
+        let relative = match p.strip_prefix(root) {
+            Ok(relative) if relative != Path::new("") => relative,
+            _ => Path::new(p.file_name().unwrap_or_default()),
+        };
+        let name = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        files.push((name, p.to_path_buf()));
+    }
+    Ok(())
+}
+
+/// Hashes the files on all available threads.
+fn hash_parallel(
+    files: Vec<(String, PathBuf, u128, u64)>,
+) -> Vec<io::Result<(String, CachedHash)>> {
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = files.len().div_ceil(threads).max(1);
+    std::thread::scope(|s| {
+        let handles = files
+            .chunks(chunk_size)
+            .map(|chunk| {
+                s.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|(path, file, modified, size)| {
+                            Ok((
+                                path.clone(),
+                                CachedHash {
+                                    modified: *modified,
+                                    size: *size,
+                                    sha256: hash_file(file)?,
+                                },
+                            ))
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .flat_map(|h| {
+                h.join()
+                    .unwrap_or_else(|_| vec![Err(io::Error::other("hashing thread panicked"))])
+            })
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str, sha256: &str) -> ManifestEntry {
+        ManifestEntry {
+            path: path.to_string(),
+            size: 1,
+            sha256: sha256.to_string(),
+        }
+    }
+
+    #[test]
+    fn diff_manifests() {
+        let ours = Manifest {
+            files: vec![entry("a", "1"), entry("b", "2"), entry("c", "3")],
+        };
+        let theirs = Manifest {
+            files: vec![entry("b", "2"), entry("c", "4"), entry("d", "5")],
+        };
+        let diff = ours.diff(&theirs);
+        assert_eq!(diff.extra, vec!["a"]);
+        assert_eq!(diff.changed, vec!["c"]);
+        assert_eq!(diff.missing, vec!["d"]);
+        assert!(ours.diff(&ours).is_empty());
     }
 
-    Ok(digest(total))
+    /// Creates an empty directory for a test.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("integrity_{name}_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn build_sorted() {
+        let dir = test_dir("sorted");
+        let root = dir.join("assets");
+        std::fs::create_dir_all(root.join("sub")).unwrap();
+        std::fs::create_dir_all(root.join("cache")).unwrap();
+        for name in ["b.txt", "a.txt", "sub/c.txt", "cache/d.txt"] {
+            std::fs::write(root.join(name), name).unwrap();
+        }
+        let cache = dir.join("hashes.json");
+
+        let first = Manifest::build(&[&root], &[&root.join("cache")], &cache).unwrap();
+        let paths = first.files.iter().map(|f| &*f.path).collect::<Vec<_>>();
+        assert_eq!(paths, ["a.txt", "b.txt", "sub/c.txt"]);
+        assert_eq!(first.files[0].sha256, hash_bytes(b"a.txt"));
+
+        let second = Manifest::build(&[&root], &[&root.join("cache")], &cache).unwrap();
+        assert_eq!(first, second);
+        assert_eq!(first.hash(), second.hash());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn build_cached() {
+        let dir = test_dir("cached");
+        let file = dir.join("assets/a.txt");
+        std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+        std::fs::write(&file, "old").unwrap();
+        let cache = dir.join("hashes.json");
+        let build = || Manifest::build(&[dir.join("assets")], &[], &cache).unwrap();
+        build();
+
+        // Unchanged files are taken from the cache without being read
+        let mut cached: HashMap<String, CachedHash> =
+            serde_json::from_slice(&std::fs::read(&cache).unwrap()).unwrap();
+        cached.get_mut("a.txt").unwrap().sha256 = "cached".to_string();
+        std::fs::write(&cache, serde_json::to_vec(&cached).unwrap()).unwrap();
+        assert_eq!(build().files[0].sha256, "cached");
+
+        // Touched files are hashed again
+        std::fs::write(&file, "new").unwrap();
+        let touched = std::time::SystemTime::now() + std::time::Duration::from_secs(60);
+        std::fs::File::options()
+            .write(true)
+            .open(&file)
+            .unwrap()
+            .set_modified(touched)
+            .unwrap();
+        assert_eq!(build().files[0].sha256, hash_bytes(b"new"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn build_unreadable() {
+        let dir = test_dir("unreadable");
+        let root = dir.join("assets");
+        std::fs::create_dir_all(&root).unwrap();
+        std::os::unix::fs::symlink(dir.join("missing"), root.join("broken.txt")).unwrap();
+
+        assert!(Manifest::build(&[&root], &[], &dir.join("hashes.json")).is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 }
@@ -2,4 +2,24 @@
 
 qwak_shared::plugin_calls!();
 pub use calls::*;
-pub use extism::{Function, PTR, UserData, ValType, host_fn};
+pub use extism::{Error, Function, PTR, UserData, ValType, convert::Msgpack, host_fn};
+
+/// Limits for a plugin, keeping a misbehaving plugin from taking the game down.
+#[derive(Debug, Clone, Copy)]
+pub struct QwakLimits {
+    /// How many instructions a single call may run, [None] for no limit.
+    pub fuel: Option<u64>,
+    /// How long a single call may run, [None] for no limit.
+    pub timeout: Option<std::time::Duration>,
+    /// The maximum memory of the plugin in 64 KiB pages, [None] for no limit.
+    pub memory_pages: Option<u32>,
+}
+impl Default for QwakLimits {
+    fn default() -> Self {
+        Self {
+            fuel: Some(1_000_000_000),
+            timeout: Some(std::time::Duration::from_secs(1)),
+            memory_pages: Some(4096),
+        }
+    }
+}
@@ -4,6 +4,7 @@
 use extism_pdk::{FromBytes, Msgpack, ToBytes};
 use faststr::FastStr;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 #[derive(Debug, Clone, FromBytes, ToBytes, Deserialize, Serialize, Default)]
 #[allow(missing_docs)]
@@ -23,6 +24,34 @@ pub struct PlayerSpawnInfo {
     pub controller_type: ControllerType,
 }
 
+/// Describes a plugin, returned by [`plugin_manifest`](../qwak_shared/trait.QwakPlugin.html#tymethod.plugin_manifest).
+#[derive(Debug, Clone, FromBytes, ToBytes, Deserialize, Serialize, Default)]
+#[encoding(Msgpack)]
+pub struct QwakManifest {
+    /// The name of the plugin.
+    pub name: String,
+    /// The version of the plugin.
+    pub version: [i32; 3],
+    /// The host API the plugin was built against, should be set to
+    /// [`HOST_API_VERSION`](../qwak_shared/constant.HOST_API_VERSION.html).
+    pub host_api: u32,
+    /// The parts of the game the plugin needs, from
+    /// [`HOST_CAPABILITIES`](../qwak_shared/constant.HOST_CAPABILITIES.html).
+    pub capabilities: Vec<String>,
+    /// Plugins which have to be loaded before this one.
+    pub dependencies: Vec<QwakDependency>,
+}
+
+/// A plugin another plugin depends on.
+#[derive(Debug, Clone, FromBytes, ToBytes, Deserialize, Serialize)]
+#[encoding(Msgpack)]
+pub struct QwakDependency {
+    /// The name of the plugin.
+    pub name: String,
+    /// The lowest version of the plugin which works.
+    pub version: [i32; 3],
+}
+
 /// The argument to [`map_interact`](../qwak_shared/trait.QwakPlugin.html#tymethod.map_interact).
 #[derive(Debug, Clone, FromBytes, ToBytes, Deserialize, Serialize)]
 #[encoding(Msgpack)]
@@ -56,6 +85,243 @@ pub struct MsgVec3 {
     pub y: f32,
     pub z: f32,
 }
+
+/// A player, returned by [`game__player__info`](../qwak_shared/trait.QwakHostFunctions.html#tymethod.game__player__info).
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PlayerState {
+    /// The id of the player.
+    pub id: u64,
+    /// The name of the player.
+    pub name: String,
+    /// Where the player is.
+    pub position: MsgVec3,
+    /// The health of the player.
+    pub health: f32,
+    /// The armor of the player.
+    pub armor: f32,
+    /// Whether the player is alive.
+    pub alive: bool,
+    /// The id of the weapon the player is holding.
+    pub weapon: Option<String>,
+    /// How much of each ammo type the player has.
+    pub ammo: HashMap<String, u32>,
+    /// The seconds left of each powerup the player has.
+    pub powerups: HashMap<String, f32>,
+}
+
+/// An entity with a target name, returned by [`game__map__targets`](../qwak_shared/trait.QwakHostFunctions.html#tymethod.game__map__targets).
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TargetEntity {
+    /// Where the entity is.
+    pub position: MsgVec3,
+    /// The rotation of the entity as a quaternion, `[x, y, z, w]`.
+    pub rotation: [f32; 4],
+    /// The scale of the entity.
+    pub scale: MsgVec3,
+}
+
+/// A HUD widget, shown with [`game__hud__set`](../qwak_shared/trait.QwakHostFunctions.html#tymethod.game__hud__set).
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HudWidget {
+    /// What the widget shows.
+    pub kind: HudKind,
+    /// Where on the screen the widget is placed.
+    pub anchor: HudAnchor,
+    /// How the widget looks.
+    #[serde(default)]
+    pub style: HudStyle,
+}
+
+/// What a [`HudWidget`] shows.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum HudKind {
+    /// A line of text.
+    Text(String),
+    /// A bar filled to `value / max`.
+    Progress {
+        /// How much of the bar is filled.
+        value: f32,
+        /// The value of a full bar.
+        max: f32,
+    },
+    /// An image, loaded from an asset path.
+    Icon(String),
+    /// A countdown which is ticked on the client.
+    Timer {
+        /// The number of seconds left.
+        seconds: f32,
+    },
+}
+
+/// Where on the screen a [`HudWidget`] is placed.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, Default)]
+#[allow(missing_docs)]
+pub enum HudAnchor {
+    TopLeft,
+    #[default]
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+/// How a [`HudWidget`] looks.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HudStyle {
+    /// The color of the widget, `[r, g, b, a]`.
+    pub color: [f32; 4],
+    /// The font size of text, or the height of bars and icons.
+    pub size: f32,
+    /// Moves the widget away from its anchor, in pixels.
+    pub offset: [f32; 2],
+}
+impl Default for HudStyle {
+    fn default() -> Self {
+        Self {
+            color: [1.0; 4],
+            size: 24.0,
+            offset: [0.0; 2],
+        }
+    }
+}
+
+/// A modal menu, opened with [`game__menu__open`](../qwak_shared/trait.QwakHostFunctions.html#tymethod.game__menu__open).
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Menu {
+    /// Shown above the buttons.
+    pub title: String,
+    /// The buttons of the menu, from top to bottom.
+    pub choices: Vec<MenuChoice>,
+}
+
+/// A button in a [`Menu`].
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MenuChoice {
+    /// Given back in [`MenuSelection::choice`] when the button is pressed.
+    pub id: String,
+    /// The text of the button.
+    pub text: String,
+}
+
+/// The argument to [`map_menu_select`](../qwak_shared/trait.QwakPlugin.html#tymethod.map_menu_select).
+#[derive(Debug, Clone, FromBytes, ToBytes, Deserialize, Serialize)]
+#[encoding(Msgpack)]
+pub struct MenuSelection {
+    /// The player who made the choice.
+    pub player_id: u64,
+    /// The name the menu was opened with.
+    pub menu: String,
+    /// The id of the pressed button.
+    pub choice: String,
+}
+
+/// A command players can run, registered with [`game__command__register`](../qwak_shared/trait.QwakHostFunctions.html#tymethod.game__command__register).
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CommandSignature {
+    /// The name, typed after the `/` in chat or in the console.
+    pub name: String,
+    /// What the command does.
+    pub description: String,
+    /// The arguments of the command, optional ones have to be last.
+    pub args: Vec<CommandArg>,
+    /// Whether only admins can run the command.
+    #[serde(default)]
+    pub admin: bool,
+}
+
+/// An argument of a [`CommandSignature`].
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CommandArg {
+    /// The name, shown in the usage of the command.
+    pub name: String,
+    /// What the argument has to be.
+    pub kind: CommandArgKind,
+    /// Whether the argument can be left out.
+    #[serde(default)]
+    pub optional: bool,
+}
+
+/// What a [`CommandArg`] has to be.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[allow(missing_docs)]
+pub enum CommandArgKind {
+    String,
+    Int,
+    Float,
+    Bool,
+    /// A player name or id.
+    Player,
+}
+
+/// A parsed argument of a [`CommandCall`].
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[allow(missing_docs)]
+pub enum CommandValue {
+    String(String),
+    Int(i64),
+    Float(f32),
+    Bool(bool),
+    /// The id of the player.
+    Player(u64),
+}
+
+/// The argument to [`plugin_command`](../qwak_shared/trait.QwakPlugin.html#tymethod.plugin_command).
+#[derive(Debug, Clone, FromBytes, ToBytes, Deserialize, Serialize)]
+#[encoding(Msgpack)]
+pub struct CommandCall {
+    /// The player who ran the command.
+    pub player_id: u64,
+    /// The name of the command.
+    pub command: String,
+    /// The arguments, in the order of the signature. Left out optional arguments are missing.
+    pub args: Vec<CommandValue>,
+}
+
+/// How long a value stored with [`game__storage__set`](../qwak_shared/trait.QwakHostFunctions.html#tymethod.game__storage__set) is kept.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub enum StorageScope {
+    /// Until another map is loaded.
+    Map,
+    /// Until the game exits.
+    Session,
+    /// Saved to disk and kept between games.
+    Persistent,
+}
+
+/// Why a host function failed, returned to the plugin which called it.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum HostError {
+    /// No player has this id.
+    UnknownPlayer(u64),
+    /// No brush has this target name.
+    UnknownTarget(String),
+    /// The map does not exist.
+    UnknownMap(String),
+    /// No pickup or projectile is defined with this id.
+    UnknownDefinition(String),
+    /// No entity spawned by a plugin has this id.
+    UnknownEntity(u64),
+    /// The call did not reach the game, or the game failed handling it.
+    Call(String),
+}
+impl std::fmt::Display for HostError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownPlayer(id) => write!(f, "no player has the id {id}"),
+            Self::UnknownTarget(target) => write!(f, "no brush has the target name {target:?}"),
+            Self::UnknownMap(map) => write!(f, "the map {map:?} does not exist"),
+            Self::UnknownDefinition(id) => write!(f, "nothing is defined with the id {id:?}"),
+            Self::UnknownEntity(id) => write!(f, "no spawned entity has the id {id}"),
+            Self::Call(e) => write!(f, "host call failed: {e}"),
+        }
+    }
+}
+impl std::error::Error for HostError {}
+
 /// The argument to [`map_player_leave`](../qwak_shared/trait.QwakPlugin.html#tymethod.map_player_leave).
 #[derive(Debug, Clone, FromBytes, ToBytes, Deserialize, Serialize)]
 #[encoding(Msgpack)]
@@ -85,8 +351,36 @@ pub struct Projectile {
 /// The type of a pickup.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum PickupType {
-    /// This will result in a weapon.
+    /// This will result in a weapon, refused if the player already has it.
     Weapon,
+    /// Heals the player, refused at `max` health.
+    Health {
+        /// The health given.
+        amount: f32,
+        /// The health the player can't be healed past.
+        max: f32,
+    },
+    /// Gives the player armor, refused at `max` armor.
+    Armor {
+        /// The armor given.
+        amount: f32,
+        /// The armor the player can't get past.
+        max: f32,
+    },
+    /// Gives the ammo named by `gives`, refused at `max` of it.
+    Ammo {
+        /// The ammo given.
+        amount: u32,
+        /// The ammo of this type the player can't get past.
+        max: u32,
+    },
+    /// Gives the powerup named by `gives`, refused at `max` seconds left.
+    Powerup {
+        /// The seconds of the powerup given.
+        amount: f32,
+        /// The seconds left the powerup can't get past.
+        max: f32,
+    },
 }
 
 /// The data for a pickup item.
@@ -96,7 +390,7 @@ pub struct PickupData {
     pub pickup_type: PickupType,
     /// The map classname.
     pub classname: FastStr,
-    /// The item you will get by picking this up.
+    /// The weapon, ammo or powerup you will get by picking this up.
     pub gives: FastStr,
     /// The model for this pickup, should be OBJ.
     pub pickup_model: FastStr,
@@ -157,6 +451,9 @@ pub struct WeaponData {
     #[serde(default)]
     /// The attack data for the second attack (right click by default).
     pub attack2: Attack,
+    /// The ammo this weapon uses, weapons without it never run out.
+    #[serde(default)]
+    pub ammo: Option<WeaponAmmo>,
     /// The start of a message when picking up a weapon.
     #[serde(default = "default_pickupmessage1")]
     pub pickup_message1: FastStr,
@@ -212,6 +509,41 @@ pub enum Attack {
     },
 }
 
+/// How a weapon uses and reloads ammo.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WeaponAmmo {
+    /// The ammo type taken from the player's reserve, the `gives` of its ammo pickups.
+    pub ammo_type: FastStr,
+    /// How much ammo fits in the magazine. With 0 the weapon shoots straight from the reserve.
+    #[serde(default)]
+    pub magazine: u32,
+    /// The ammo used by the first attack.
+    #[serde(default = "WeaponAmmo::default_per_shot")]
+    pub per_shot1: u32,
+    /// The ammo used by the second attack.
+    #[serde(default = "WeaponAmmo::default_per_shot")]
+    pub per_shot2: u32,
+    /// The reserve ammo given along with the weapon.
+    #[serde(default)]
+    pub given: u32,
+    /// How long reloading the magazine takes, the reload animation is played meanwhile.
+    #[serde(default = "WeaponData::default_firetime")]
+    pub reload_time: f32,
+    /// Whether the weapon reloads by itself when the magazine can't fit another shot,
+    /// otherwise the player has to press the reload key.
+    #[serde(default = "WeaponAmmo::default_auto_reload")]
+    pub auto_reload: bool,
+}
+impl WeaponAmmo {
+    fn default_per_shot() -> u32 {
+        1
+    }
+
+    fn default_auto_reload() -> bool {
+        true
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 /// The weapon animation data.
 pub struct WeaponAnimations {
@@ -3,47 +3,26 @@ use proc_macro2::{Span, TokenStream};
 use quote::quote;
 use std::collections::BTreeMap;
 use syn::{
-    FnArg, Ident, ItemTrait, Pat, Signature, TraitItem, Type, punctuated::Punctuated, token::Comma,
+    FnArg, Ident, ItemTrait, Pat, ReturnType, Signature, TraitItem, Type, punctuated::Punctuated,
+    token::Comma,
 };
 
-fn stringify(ty: &Type) -> String {
-    match ty {
-        Type::Path(type_path) => type_path
-            .path
-            .segments
-            .iter()
-            .map(|ps| ps.ident.to_string())
-            .collect::<Vec<_>>()
-            .join("::"),
-        Type::Array(_) => todo!("type_array"),
-        Type::BareFn(_) => todo!("type_bare_fn"),
-        Type::Group(_) => todo!("type_group"),
-        Type::ImplTrait(_) => todo!("type_impl_trait"),
-        Type::Infer(_) => todo!("type_infer"),
-        Type::Macro(_) => todo!("type_macro"),
-        Type::Never(_) => todo!("type_never"),
-        Type::Paren(_) => todo!("type_paren"),
-        Type::Ptr(_) => todo!(")"),
-        Type::Reference(_) => todo!("type_reference"),
-        Type::Slice(_) => todo!("type_slice"),
-        Type::TraitObject(_) => todo!("type_trait_object"),
-        Type::Tuple(_) => todo!("type_tuple"),
-        Type::Verbatim(_) => todo!("token_stream"),
-        _ => panic!(),
+/// Wraps the arguments and return type of `sig` in `wrapper`, so every value is
+/// passed through msgpack no matter its type.
+fn msgpack_sig(sig: &Signature, wrapper: &TokenStream) -> Signature {
+    let mut sig = sig.clone();
+    for input in &mut sig.inputs {
+        let FnArg::Typed(pt) = input else {
+            panic!("self arguments are not allowed");
+        };
+        let ty = &pt.ty;
+        *pt.ty = syn::parse(quote!(#wrapper<#ty>).into()).unwrap();
     }
-}
-fn get_type(ty: &Type) -> Type {
-    let ty = stringify(ty);
-    let id = match &*ty {
-        "String" => quote!(qwak::PTR),
-        "qwak_helper_types::MapInteraction" => quote!(qwak::PTR),
-        "qwak_helper_types::MsgVec3" => quote!(qwak::PTR),
-        "u32" => quote!(qwak::ValType::I64),
-        "u64" => quote!(qwak::ValType::I64),
-        "f32" => quote!(qwak::ValType::I64), // this is most likely a bug in extism?
-        _ => panic!("undefined type conversion for \"{ty}\""),
-    };
-    syn::parse(id.into()).unwrap()
+    if let ReturnType::Type(_, ty) = &mut sig.output {
+        let og = &*ty;
+        *ty = syn::parse(quote!(#wrapper<#og>).into()).unwrap();
+    }
+    sig
 }
 
 pub fn get_export_functions(item: TS) -> TS {
@@ -55,7 +34,7 @@ pub fn get_export_functions(item: TS) -> TS {
         let TraitItem::Fn(func) = func else {
             panic!("only functions are supported")
         };
-        let sig = func.sig;
+        let sig = msgpack_sig(&func.sig, &quote!(qwak::Msgpack));
         let func_name = sig.ident.clone();
         let string_name = format!("{func_name}");
         let inputs = &sig.inputs;
@@ -69,28 +48,37 @@ pub fn get_export_functions(item: TS) -> TS {
             let Pat::Ident(id) = &*pt.pat else {
                 panic!("only idents are allowed")
             };
-            ins.insert(0, get_type(&pt.ty));
-            args.push(id.ident.clone());
+            let id = &id.ident;
+            ins.push(quote!(qwak::PTR));
+            args.push(quote!(#id.into_inner()));
         });
-        match sig.output {
-            syn::ReturnType::Default => {}
-            syn::ReturnType::Type(_, ty) => out.push(get_type(&ty)),
-        }
+        let call = quote!(super::$name:: #func_name (&mut context, #args));
+        let (output, call) = match &sig.output {
+            ReturnType::Default => (quote!(()), call),
+            ReturnType::Type(_, ty) => {
+                out.push(quote!(qwak::PTR));
+                (quote!(#ty), quote!(qwak::Msgpack(#call)))
+            }
+        };
         funcs = quote! {
             #funcs
             qwak::Function::new(
                 #string_name,
                 [#ins],
                 [#out],
-                qwak::UserData::Rust(std::sync::Mutex::new(()).into()),
+                context.clone(),
                 #func_name
             ),
         };
         defs = quote! {
             #defs
-            qwak::host_fn!(pub #func_name(#inputs) -> () {
-                use qwak_shared::QwakHostFunctions;
-                Ok(super::$name:: #func_name (#args))
+            qwak::host_fn!(pub #func_name(context: $context; #inputs) -> #output {
+                let context = context.get()?;
+                let mut context = match context.lock() {
+                    Ok(o) => o,
+                    Err(e) => e.into_inner(),
+                };
+                Ok(#call)
             });
         };
     }
@@ -98,9 +86,12 @@ pub fn get_export_functions(item: TS) -> TS {
         /// Generates the interface for calling host functions.
         #[macro_export]
         macro_rules! host_gen {
-            ($name:ident) => {
+            ($name:ident, $context:ty) => {
                 pub mod inner {
-                    pub fn functions() -> impl IntoIterator<Item = qwak::Function> {
+                    #[allow(unused_imports)]
+                    use super::*;
+                    /// The host functions, which share `context`.
+                    pub fn functions(context: &qwak::UserData<$context>) -> impl IntoIterator<Item = qwak::Function> {
                         [#funcs]
                     }
                     #defs
@@ -159,6 +150,19 @@ fn get_tree(
         get_tree(&names[1..], og_name, signature, attrs, entry);
     }
 }
+/// Whether `sig` returns a `Result`.
+fn returns_result(sig: &Signature) -> bool {
+    let ReturnType::Type(_, ty) = &sig.output else {
+        return false;
+    };
+    let Type::Path(path) = &**ty else {
+        return false;
+    };
+    path.path
+        .segments
+        .last()
+        .is_some_and(|s| s.ident == "Result")
+}
 fn build_structure(ns: &Namespace, res: &mut TokenStream, depth: usize) {
     let mut inner = quote! {};
 
@@ -175,7 +179,7 @@ fn build_structure(ns: &Namespace, res: &mut TokenStream, depth: usize) {
         let panic_handler = match name != "log__debug" {
             true => {
                 quote! {
-                    #namespace_prefix inner::log__debug(format!("plugin crashed calling host function: {e}")).unwrap();
+                    #namespace_prefix inner::log__debug(extism_pdk::Msgpack(format!("plugin crashed calling host function: {e}"))).unwrap();
                 }
             }
             false => quote! {},
@@ -191,9 +195,24 @@ fn build_structure(ns: &Namespace, res: &mut TokenStream, depth: usize) {
                 let Pat::Ident(id) = &*pt.pat else {
                     panic!("only idents are allowed")
                 };
-                id.ident.clone()
+                let id = &id.ident;
+                quote!(extism_pdk::Msgpack(#id))
             })
             .collect::<Punctuated<_, Comma>>();
+        let unwrap = match sig.output {
+            ReturnType::Default => quote!(o),
+            ReturnType::Type(..) => quote!(o.into_inner()),
+        };
+        // Functions returning a result hand failed calls to the plugin instead of panicking
+        let on_error = match returns_result(sig) {
+            true => quote! {
+                Err(qwak_helper_types::HostError::Call(format!("{e}")))
+            },
+            false => quote! {{
+                #panic_handler
+                panic!()
+            }},
+        };
 
         inner = quote! {
             #inner
@@ -201,11 +220,8 @@ fn build_structure(ns: &Namespace, res: &mut TokenStream, depth: usize) {
             pub #new_sig {
                 unsafe {
                     match #namespace_prefix inner::#og_name(#args) {
-                        Ok(o) => o,
-                        Err(e) => {
-                            #panic_handler
-                            panic!()
-                        },
+                        Ok(o) => #unwrap,
+                        Err(e) => #on_error,
                     }
                 }
             }
@@ -261,7 +277,7 @@ pub fn get_host_calls(item: TS) -> TS {
         let TraitItem::Fn(func) = func else {
             panic!("only functions are supported")
         };
-        let og_sig = &func.sig;
+        let og_sig = msgpack_sig(&func.sig, &quote!(extism_pdk::Msgpack));
 
         exports = quote! {
             #exports
@@ -158,23 +158,64 @@ pub fn get_plugin_calls(item: TS) -> TS {
                     /// A WASM qwak plugin.
                     #[derive(Debug)]
                     pub struct QwakPlugin {
-                        inner: std::sync::Arc<std::sync::Mutex<extism::Plugin>>
+                        inner: std::sync::Arc<std::sync::Mutex<extism::Plugin>>,
+                        manifest: qwak_helper_types::QwakManifest,
                     }
                     impl QwakPlugin {
                         /// Returns a new [QwakPlugin].
                         /// 
                         /// # Errors
-                        /// Fails if the path does not exist or if the binary files is invalid.
-                        pub fn new(path: impl AsRef<std::path::Path>, functions: impl IntoIterator<Item = extism::Function>) -> Result<Self, String> {
-                            let wasm = extism::Wasm::file(path);
+                        /// Fails if the path does not exist, if the binary files is invalid
+                        /// or if the plugin was built for another version of the game.
+                        pub fn new(path: impl AsRef<std::path::Path>, functions: impl IntoIterator<Item = extism::Function>, limits: &crate::QwakLimits) -> Result<Self, String> {
+                            Self::from_wasm(extism::Wasm::file(path), functions, limits)
+                        }
+
+                        /// Returns a new [QwakPlugin] from an already read binary.
+                        ///
+                        /// # Errors
+                        /// Fails if the binary is invalid or if the plugin was built for another version of the game.
+                        pub fn from_data(data: Vec<u8>, functions: impl IntoIterator<Item = extism::Function>, limits: &crate::QwakLimits) -> Result<Self, String> {
+                            Self::from_wasm(extism::Wasm::data(data), functions, limits)
+                        }
+
+                        fn from_wasm(wasm: extism::Wasm, functions: impl IntoIterator<Item = extism::Function>, limits: &crate::QwakLimits) -> Result<Self, String> {
                             let mut manifest = extism::Manifest::new([wasm]);
-                            manifest.memory.max_pages = Some(u32::MAX);
+                            manifest.memory.max_pages = Some(limits.memory_pages.unwrap_or(u32::MAX));
                             manifest.memory.max_var_bytes = Some(u32::MAX as u64);
-                            let plug = Arc::new(Mutex::new(
-                                extism::Plugin::new(&manifest, functions, true)
-                                    .map_err(|e| format!("{e}"))?,
-                            ));
-                            Ok(QwakPlugin { inner: plug })
+                            manifest.timeout_ms = limits.timeout.map(|t| t.as_millis() as u64);
+                            let mut builder = extism::PluginBuilder::new(manifest)
+                                .with_wasi(true)
+                                .with_functions(functions);
+                            if let Some(fuel) = limits.fuel {
+                                builder = builder.with_fuel_limit(fuel);
+                            }
+                            let plug = builder.build().map_err(|e| format!("{e}"))?;
+                            if !plug.function_exists("plugin_plugin_manifest") {
+                                return Err("the plugin has no manifest, it was built for an older version of the game".to_string());
+                            }
+                            let mut plug = QwakPlugin {
+                                inner: Arc::new(Mutex::new(plug)),
+                                manifest: Default::default(),
+                            };
+                            let manifest = plug.plugin_manifest().map_err(|e| format!("{e}"))?;
+                            qwak_shared::check_manifest(&manifest)?;
+                            plug.manifest = manifest;
+                            Ok(plug)
+                        }
+
+                        /// Returns the manifest the plugin was loaded with.
+                        pub fn manifest(&self) -> &qwak_helper_types::QwakManifest {
+                            &self.manifest
+                        }
+
+                        /// Whether the plugin exports `function`, as optional functions may be missing.
+                        pub fn exports(&self, function: &str) -> bool {
+                            let name = format!("plugin_{function}");
+                            match self.inner.lock() {
+                                Ok(o) => o.function_exists(name),
+                                Err(e) => e.into_inner().function_exists(name),
+                            }
                         }
 
                         #res
@@ -3,20 +3,56 @@
 
 #![allow(clippy::unused_unit)]
 
+/// The version of [QwakPlugin] and [QwakHostFunctions], increased whenever either changes.
+/// Plugins built against another version are refused.
+pub const HOST_API_VERSION: u32 = 12;
+
+/// The capabilities the game provides, which plugins can require in their manifest.
+pub const HOST_CAPABILITIES: &[&str] = &[
+    "audio", "brush", "command", "entity", "hud", "map", "menu", "message", "player", "storage",
+    "tick",
+];
+
+/// Checks that a plugin was built for this version of the game.
+/// ## Errors
+/// Returns why the plugin can't be loaded.
+pub fn check_manifest(manifest: &qwak_helper_types::QwakManifest) -> Result<(), String> {
+    if manifest.host_api != HOST_API_VERSION {
+        return Err(format!(
+            "\"{}\" was built for host API version {}, but the game provides version {HOST_API_VERSION}",
+            manifest.name, manifest.host_api
+        ));
+    }
+    if let Some(missing) = manifest
+        .capabilities
+        .iter()
+        .find(|c| !HOST_CAPABILITIES.contains(&c.as_str()))
+    {
+        return Err(format!(
+            "\"{}\" requires the capability \"{missing}\", which the game does not provide",
+            manifest.name
+        ));
+    }
+    Ok(())
+}
+
 /// The functions a plugin needs to define.
 #[qwak_macro::plugin]
 pub trait QwakPlugin {
     #[doc = "Called when a plugin is loaded. Can be used to call functions which for example sets up your runtime etc."]
     fn plugin_init() -> ();
-    #[doc = "Returns the name of a plugin."]
-    fn plugin_name() -> String;
-    #[doc = "Returns the version of a plugin."]
-    fn plugin_version() -> [i32; 3];
+    #[doc = "Returns the name, version and requirements of a plugin. Called before any other function."]
+    fn plugin_manifest() -> qwak_helper_types::QwakManifest;
+
+    #[doc = "Returns the state of a plugin before it is hot-reloaded."]
+    fn plugin_save_state() -> Vec<u8>;
+    #[doc = "Gives a hot-reloaded plugin the state saved by the old version of it. Called after `plugin_init`."]
+    fn plugin_restore_state(state: Vec<u8>) -> ();
 
     #[doc = "Returns information about how players should be set up."]
     fn player_info() -> qwak_helper_types::PlayerSpawnInfo;
 
-    #[doc = "Function called by the game when a map is loaded."]
+    #[doc = "Function called by the game when a map is loaded. Players staying from the previous map are not joined again."]
     fn map_init() -> ();
     #[doc = "The function which defines the scripts `interactable` entities can call in a map."]
     fn map_interact(args: qwak_helper_types::MapInteraction) -> ();
@@ -30,6 +66,14 @@ pub trait QwakPlugin {
     fn map_player_join(id: u64) -> ();
     #[doc = "Function called by the game when a player leaves."]
     fn map_player_leave(args: qwak_helper_types::PlayerLeave) -> ();
+    #[doc = "Called by the server at a fixed rate with the seconds since the last tick."]
+    #[doc = "Only called for plugins with the `tick` capability."]
+    fn map_tick(_dt: f32) -> () {}
+    #[doc = "Called when a player presses a button in a menu opened by `game__menu__open`."]
+    fn map_menu_select(_args: qwak_helper_types::MenuSelection) -> () {}
+    #[doc = "Called when a player runs a command registered by `game__command__register`."]
+    #[doc = "Every plugin is called, so ignore the commands registered by other plugins."]
+    fn plugin_command(_args: qwak_helper_types::CommandCall) -> () {}
 
     #[doc = "The projectiles this plugin defines."]
     fn plugin_get_projectiles()
@@ -42,9 +86,17 @@ pub trait QwakPlugin {
     -> std::collections::HashMap<faststr::FastStr, qwak_helper_types::WeaponData>;
 }
 
-#[allow(clippy::unnecessary_safety_doc, non_snake_case)]
+#[allow(non_snake_case)]
 #[qwak_macro::host]
 /// The functions a the game defines for plugin -> game interaction.
+///
+/// The game implements each of them with a context as the first argument,
+/// which the requests are queued in and applied to the world after the plugin call.
+/// Arguments and return values are passed as msgpack, so any type implementing
+/// `Serialize` and `Deserialize` can be used.
+///
+/// Functions returning a `Result` give the plugin a [HostError](qwak_helper_types::HostError)
+/// when they fail, also if the call itself fails. Other functions panic in the plugin if the call fails.
 pub trait QwakHostFunctions {
     #[doc = "Log an error."]
     fn log__error(message: String);
@@ -52,49 +104,135 @@ pub trait QwakHostFunctions {
     fn log__debug(val: String);
 
     #[doc = "Sends a message to all players."]
-    #[doc = "# Safety"]
-    #[doc = "Will segfault if ran outside of the game."]
     fn game__broadcast_message(val: String);
+    #[doc = "Sends a message to a specific player."]
+    fn game__player__message(id: u64, val: String) -> Result<(), qwak_helper_types::HostError>;
     #[doc = "Returns the player name of a specified id."]
-    #[doc = "# Safety"]
-    #[doc = "Will segfault if ran outside of the game."]
-    fn game__player__get_name(id: u64) -> String;
+    fn game__player__get_name(id: u64) -> Result<String, qwak_helper_types::HostError>;
     #[doc = "Move a brush by the vector provided."]
-    #[doc = "# Safety"]
-    #[doc = "Will segfault if ran outside of the game."]
-    fn game__brush__translate(target: String, x: f32, y: f32, z: f32, duration: u32);
+    fn game__brush__translate(
+        target: String,
+        x: f32,
+        y: f32,
+        z: f32,
+        duration: u32,
+    ) -> Result<(), qwak_helper_types::HostError>;
     #[doc = "Rotate a brush by the vector provided."]
-    #[doc = "# Safety"]
-    #[doc = "Will segfault if ran outside of the game."]
-    fn game__brush__rotate(target: String, x: f32, y: f32, z: f32, duration: u32);
+    fn game__brush__rotate(
+        target: String,
+        x: f32,
+        y: f32,
+        z: f32,
+        duration: u32,
+    ) -> Result<(), qwak_helper_types::HostError>;
     #[doc = "Plays a sound effect globaly."]
-    #[doc = "# Safety"]
-    #[doc = "Will segfault if ran outside of the game."]
     fn game__audio__global__play(path: String, volume: f32);
     #[doc = "Run a MapInteract after a set amount of time."]
     fn game__map__timeout(map_int: qwak_helper_types::MapInteraction, delay: u32);
     #[doc = "Hurt a specific player."]
-    #[doc = "# Safety"]
-    #[doc = "Will segfault if ran outside of the game."]
-    fn game__player__hurt(id: u64, damage: f32);
+    fn game__player__hurt(id: u64, damage: f32) -> Result<(), qwak_helper_types::HostError>;
     #[doc = "Heal a specific player."]
-    #[doc = "# Safety"]
-    #[doc = "Will segfault if ran outside of the game."]
-    fn game__player__heal(id: u64, damage: f32);
+    fn game__player__heal(id: u64, damage: f32) -> Result<(), qwak_helper_types::HostError>;
     #[doc = "Set player health."]
-    #[doc = "# Safety"]
-    #[doc = "Will segfault if ran outside of the game."]
-    fn game__player__set_stats(id: u64, health: f32, armor: f32);
-    #[doc = "Teleport player to the specified location."]
-    #[doc = "# Safety"]
-    #[doc = "Will segfault if ran outside of the game."]
-    fn game__player__teleport(id: u64, x: f32, y: f32, z: f32);
+    fn game__player__set_stats(
+        id: u64,
+        health: f32,
+        armor: f32,
+    ) -> Result<(), qwak_helper_types::HostError>;
     #[doc = "Teleport player to the specified location."]
-    #[doc = "# Safety"]
-    #[doc = "Will segfault if ran outside of the game."]
+    fn game__player__teleport(
+        id: u64,
+        x: f32,
+        y: f32,
+        z: f32,
+    ) -> Result<(), qwak_helper_types::HostError>;
+    #[doc = "Returns the spawn point of the map."]
     fn game__map__spawn_point() -> qwak_helper_types::MsgVec3;
+    #[doc = "Changes the map for every player. An empty path picks the next map in the rotation."]
+    fn game__map__change(path: String) -> Result<(), qwak_helper_types::HostError>;
     #[doc = "Call this to get the id of the host."]
-    #[doc = "# Safety"]
-    #[doc = "Will segfault if ran outside of the game."]
     fn game__host_id() -> u64;
+
+    #[doc = "Returns the ids of the connected players."]
+    fn game__player__list() -> Vec<u64>;
+    #[doc = "Returns where a player is, their health, armor, whether they are alive and their weapon."]
+    fn game__player__info(
+        id: u64,
+    ) -> Result<qwak_helper_types::PlayerState, qwak_helper_types::HostError>;
+    #[doc = "Returns the entities with the target name and their transforms."]
+    fn game__map__targets(
+        target: String,
+    ) -> Result<Vec<qwak_helper_types::TargetEntity>, qwak_helper_types::HostError>;
+    #[doc = "Returns the path of the current map."]
+    fn game__map__name() -> String;
+    #[doc = "Returns how many seconds the current map has been played."]
+    fn game__map__time() -> f32;
+
+    #[doc = "Spawns a pickup defined by a plugin, returning the id of the entity."]
+    fn game__entity__spawn_pickup(
+        pickup: String,
+        position: qwak_helper_types::MsgVec3,
+    ) -> Result<u64, qwak_helper_types::HostError>;
+    #[doc = "Spawns a projectile defined by a plugin flying towards `dir`, returning the id of the entity."]
+    fn game__entity__spawn_projectile(
+        projectile: String,
+        position: qwak_helper_types::MsgVec3,
+        dir: qwak_helper_types::MsgVec3,
+    ) -> Result<u64, qwak_helper_types::HostError>;
+    #[doc = "Places a GLTF model, returning the id of the entity."]
+    fn game__entity__spawn_prop(
+        model: String,
+        position: qwak_helper_types::MsgVec3,
+        scale: f32,
+    ) -> u64;
+    #[doc = "Removes an entity spawned by a plugin."]
+    fn game__entity__despawn(id: u64) -> Result<(), qwak_helper_types::HostError>;
+
+    #[doc = "Creates or replaces the named HUD widget, for one player or everyone if `player` is `None`."]
+    fn game__hud__set(
+        player: Option<u64>,
+        name: String,
+        widget: qwak_helper_types::HudWidget,
+    ) -> Result<(), qwak_helper_types::HostError>;
+    #[doc = "Removes the named HUD widget, for one player or everyone if `player` is `None`."]
+    fn game__hud__remove(
+        player: Option<u64>,
+        name: String,
+    ) -> Result<(), qwak_helper_types::HostError>;
+
+    #[doc = "Opens a menu for one player or everyone, replacing an open menu with the same name."]
+    #[doc = "The pressed button is given to `map_menu_select`, which also closes the menu."]
+    fn game__menu__open(
+        player: Option<u64>,
+        name: String,
+        menu: qwak_helper_types::Menu,
+    ) -> Result<(), qwak_helper_types::HostError>;
+    #[doc = "Closes the named menu, for one player or everyone if `player` is `None`."]
+    fn game__menu__close(
+        player: Option<u64>,
+        name: String,
+    ) -> Result<(), qwak_helper_types::HostError>;
+
+    #[doc = "Registers a command players can run from chat with a `/` prefix or from the console."]
+    #[doc = "The arguments are checked against the signature before `plugin_command` is called."]
+    fn game__command__register(command: qwak_helper_types::CommandSignature);
+
+    #[doc = "Returns the msgpack value stored under `key`, the storage is shared by every plugin."]
+    fn game__storage__get(scope: qwak_helper_types::StorageScope, key: String) -> Option<Vec<u8>>;
+    #[doc = "Stores a msgpack value under `key`, or removes it if `value` is `None`."]
+    fn game__storage__set(
+        scope: qwak_helper_types::StorageScope,
+        key: String,
+        value: Option<Vec<u8>>,
+    );
+    #[doc = "Stores `value` only if the current value is `expected`, returning whether it was stored."]
+    #[doc = "Used to update a value without overwriting changes made by other plugins."]
+    fn game__storage__compare_and_swap(
+        scope: qwak_helper_types::StorageScope,
+        key: String,
+        expected: Option<Vec<u8>>,
+        value: Option<Vec<u8>>,
+    ) -> bool;
+    #[doc = "Returns the stored keys starting with `prefix`, sorted."]
+    fn game__storage__keys(scope: qwak_helper_types::StorageScope, prefix: String) -> Vec<String>;
 }
@@ -1,7 +1,6 @@
 use bevy::ecs::system::Resource;
 use std::{
     fmt::Debug,
-    fs::File,
     io::{self, Read},
     marker::PhantomData,
     path::Path,
@@ -27,11 +26,11 @@ impl<Type> Debug for Entropy<Type> {
 }
 /// Load the entropy for game actions.
 pub fn entropy_game() -> Entropy<EGame> {
-    Entropy::load("assets/game.entropy").unwrap()
+    Entropy::load("game.entropy").unwrap()
 }
 /// Load the entropy for misc actions.
 pub fn entropy_misc() -> Entropy<EMisc> {
-    Entropy::load("assets/game.entropy").unwrap()
+    Entropy::load("game.entropy").unwrap()
 }
 impl<Type> Entropy<Type> {
     /// Returns a "random" float between 0.0 and 1.0
@@ -68,7 +67,8 @@ impl<Type> Entropy<Type> {
     }
 
     fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
-        let mut f = File::open(path)?;
+        let data = vfs::read(path)?;
+        let mut f = data.as_slice();
         let mut amount = [0u8; 4];
         f.read_exact(&mut amount)?;
         let amount = u32::from_ne_bytes(amount);
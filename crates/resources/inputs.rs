@@ -10,6 +10,7 @@ pub struct PlayerInput {
     weapon_shoot2: Key,
     weapon_next: Key,
     weapon_previous: Key,
+    weapon_reload: Key,
     interact: Key,
     walk_forward: Key,
     walk_backward: Key,
@@ -31,10 +32,12 @@ pub struct PlayerInput {
     weapon_slot9: Key,
     weapon_slot10: Key,
     show_lobby: Key,
+    chat: Key,
+    console: Key,
 }
 impl Default for PlayerInput {
     fn default() -> Self {
-        serde_json::from_str(&std::fs::read_to_string("assets/inputs.json").unwrap()).unwrap()
+        serde_json::from_str(&vfs::read_to_string("inputs.json").unwrap()).unwrap()
     }
 }
 
@@ -8,12 +8,18 @@ use bevy::{
 };
 use faststr::FastStr;
 use qwak_helper_types::{PickupData, Projectile, WeaponData};
-use std::{collections::HashMap, ops::Deref, path::PathBuf};
+use std::{
+    collections::HashMap,
+    ops::Deref,
+    path::{Path, PathBuf},
+};
 
 /// Contains the structs for randomeness.
 pub mod entropy;
 /// Contains the struct for player input.
 pub mod inputs;
+/// Contains the map wide settings.
+pub mod map_settings;
 
 /// Represents the current game stage
 #[derive(Debug, Resource, PartialEq, Eq, States, Default, Hash, Clone, Copy)]
@@ -31,6 +37,42 @@ pub enum CurrentStage {
 #[derive(Debug, Resource)]
 pub struct CurrentMap(pub PathBuf);
 
+/// The maps a server cycles through, read from `assets/map_rotation.txt`.
+#[derive(Debug, Resource, Default)]
+pub struct MapRotation(pub Vec<PathBuf>);
+impl MapRotation {
+    /// Reads the rotation, one map per line. Empty lines and lines starting with `#` are skipped.
+    pub fn load() -> Self {
+        let Ok(file) = vfs::read_to_string("map_rotation.txt") else {
+            return Self::default();
+        };
+        Self(
+            file.lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                .map(PathBuf::from)
+                .collect(),
+        )
+    }
+
+    /// Returns the map after `current`, or the first map if `current` is not in the rotation.
+    pub fn next(&self, current: &Path) -> Option<PathBuf> {
+        let next = match self.0.iter().position(|m| m == current) {
+            Some(i) => (i + 1) % self.0.len(),
+            None => 0,
+        };
+        self.0.get(next).cloned()
+    }
+}
+
+/// Requests the server to change map. `None` picks the next map in the [MapRotation].
+#[derive(Debug, Resource)]
+pub struct ChangeMap(pub Option<PathBuf>);
+
+/// Exists while the current map is being torn down for a new one, without leaving the game.
+#[derive(Debug, Resource)]
+pub struct ChangingMap;
+
 /// Represents the pause state of the game
 #[derive(Debug, Resource)]
 pub struct Paused(pub bool);
@@ -44,15 +86,6 @@ pub fn if_not_paused(val: Res<Paused>) -> bool {
 #[derive(Resource)]
 pub struct MapFirstRun(pub bool);
 
-/// True if the map is done and loaded
-#[derive(Resource)]
-pub struct MapDoneLoading(pub bool);
-
-/// Returns if the map is done loading.
-pub fn if_map_done_loading(val: Res<MapDoneLoading>) -> bool {
-    val.0
-}
-
 /// True if the player has been spawned
 #[derive(Resource)]
 pub struct PlayerSpawned(pub bool);
@@ -70,29 +103,63 @@ pub struct PlayerSpawnpoint(pub Vec3);
 #[derive(Debug, Resource, Default)]
 pub struct TexturesLoading(pub Vec<UntypedHandle>);
 
-/// The different states for texture loading.
-#[derive(Debug, Resource)]
-pub enum TextureLoadingState {
-    /// Textures are not loaded.
+/// The different stages of loading a map.
+#[derive(Debug, Resource, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MapLoadingState {
+    /// Nothing is loaded.
+    #[default]
     NotLoaded,
     /// Textures are loading.
-    Loading,
-    /// Textures are loaded.
+    LoadingTextures,
+    /// The map geometry is being built in the background and added to the world.
+    BuildingGeometry,
+    /// The map is done and loaded.
     Done,
 }
+impl std::fmt::Display for MapLoadingState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MapLoadingState::NotLoaded => write!(f, "reading map"),
+            MapLoadingState::LoadingTextures => write!(f, "loading textures"),
+            MapLoadingState::BuildingGeometry => write!(f, "building geometry"),
+            MapLoadingState::Done => write!(f, "done"),
+        }
+    }
+}
 
-/// Check if textures are not loaded.
-#[allow(dead_code)]
-pub fn if_textures_not_loaded(text: Res<TextureLoadingState>) -> bool {
-    matches!(*text, TextureLoadingState::NotLoaded)
+/// How far along the current [MapLoadingState] is.
+#[derive(Debug, Resource, Default)]
+pub struct MapLoadingProgress {
+    /// The amount of finished items.
+    pub done: usize,
+    /// The total amount of items.
+    pub total: usize,
+}
+impl MapLoadingProgress {
+    /// Returns the progress between 0.0 and 1.0.
+    pub fn fraction(&self) -> f32 {
+        match self.total {
+            0 => 0.0,
+            total => self.done as f32 / total as f32,
+        }
+    }
+}
+
+/// Check if the map is not loaded.
+pub fn if_map_not_loaded(state: Res<MapLoadingState>) -> bool {
+    matches!(*state, MapLoadingState::NotLoaded)
 }
 /// Check if textures are loading.
-pub fn if_texture_loading(text: Res<TextureLoadingState>) -> bool {
-    matches!(*text, TextureLoadingState::Loading)
+pub fn if_texture_loading(state: Res<MapLoadingState>) -> bool {
+    matches!(*state, MapLoadingState::LoadingTextures)
+}
+/// Check if the map geometry is being built.
+pub fn if_building_geometry(state: Res<MapLoadingState>) -> bool {
+    matches!(*state, MapLoadingState::BuildingGeometry)
 }
-/// Check if textures are loaded.
-pub fn if_texture_done_loading(text: Res<TextureLoadingState>) -> bool {
-    matches!(*text, TextureLoadingState::Done)
+/// Returns if the map is done loading.
+pub fn if_map_done_loading(state: Res<MapLoadingState>) -> bool {
+    matches!(*state, MapLoadingState::Done)
 }
 
 /// A map which provides Path -> Handle for textures
@@ -0,0 +1,80 @@
+use bevy::{math::Vec3, pbr::AmbientLight, prelude::Resource};
+use faststr::FastStr;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Map wide settings, read from the keys of the worldspawn entity.
+/// Owned by the server and sent to clients together with the map.
+#[derive(Debug, Resource, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MapSettings {
+    /// The title of the map.
+    pub message: FastStr,
+    /// Who made the map.
+    pub author: FastStr,
+    /// The downwards acceleration applied to players.
+    pub gravity: f32,
+    /// The fog color, as `r g b` between 0 and 1.
+    pub fog_color: Vec3,
+    /// The fog density, fog is disabled if this is 0.
+    pub fog_density: f32,
+    /// The brightness of the ambient light.
+    pub ambient: f32,
+    /// Path to a sound which is looped while playing the map.
+    pub music: Option<FastStr>,
+    /// A solid sky color, the atmosphere is used if this is not set.
+    pub sky: Option<Vec3>,
+    /// The gamemode the map is made for.
+    pub gamemode: Option<FastStr>,
+}
+impl Default for MapSettings {
+    fn default() -> Self {
+        Self {
+            message: FastStr::default(),
+            author: FastStr::default(),
+            gravity: -15.0,
+            fog_color: Vec3::ONE,
+            fog_density: 0.0,
+            ambient: AmbientLight::default().brightness,
+            music: None,
+            sky: None,
+            gamemode: None,
+        }
+    }
+}
+impl MapSettings {
+    /// Reads the settings from the worldspawn attributes.
+    /// Missing or malformed keys fall back to their defaults.
+    pub fn from_attributes(attributes: &HashMap<FastStr, FastStr>) -> Self {
+        let default = Self::default();
+        let get = |key: &str| {
+            attributes
+                .get(&FastStr::from(key))
+                .cloned()
+                .take_if(|s| !s.is_empty())
+        };
+        let float = |key: &str, default: f32| {
+            get(key)
+                .and_then(|s| s.parse::<f32>().ok())
+                .unwrap_or(default)
+        };
+
+        Self {
+            message: get("message").unwrap_or(default.message),
+            author: get("author").unwrap_or(default.author),
+            gravity: float("gravity", default.gravity),
+            fog_color: get("fog_color")
+                .and_then(|s| parse_color(&s))
+                .unwrap_or(default.fog_color),
+            fog_density: float("fog_density", default.fog_density),
+            ambient: float("ambient", default.ambient),
+            music: get("music"),
+            sky: get("sky").and_then(|s| parse_color(&s)),
+            gamemode: get("gamemode"),
+        }
+    }
+}
+
+fn parse_color(str: &str) -> Option<Vec3> {
+    let mut splat = str.split_whitespace().map(|s| s.parse::<f32>().ok());
+    Some(Vec3::new(splat.next()??, splat.next()??, splat.next()??))
+}
@@ -0,0 +1,286 @@
+//! A virtual filesystem which overlays `.opk` archives and directories.
+//!
+//! The game reads its assets through [get], which mounts every archive in `packs`
+//! in name order, and the asset directory of the running qwak on top of them.
+//! Files in later mounts replace the ones in earlier mounts, so a mod pack
+//! like `packs/1-mod.opk` overrides the base `packs/0-base.opk`.
+//!
+//! Paths are relative to the asset root, a leading `assets/` is ignored.
+
+use std::{
+    collections::BTreeSet,
+    io,
+    path::{Component, Path, PathBuf},
+    sync::OnceLock,
+};
+
+pub mod opk;
+pub mod reader;
+
+pub use opk::Opk;
+pub use reader::VfsAssetReader;
+
+/// The directory containing the archives mounted by [get].
+pub const PACK_DIR: &str = "packs";
+/// The directory mounted on top of the archives by [get], unless [init] is called.
+pub const ASSET_DIR: &str = "assets";
+/// The file extension of archives in [PACK_DIR].
+pub const PACK_EXTENSION: &str = "opk";
+
+static VFS: OnceLock<Vfs> = OnceLock::new();
+
+/// Sets up the filesystem used by the game, with `asset_dir` mounted on top of the archives.
+/// Does nothing if the filesystem has already been used.
+pub fn init<P: AsRef<Path>>(asset_dir: P) {
+    if VFS.set(Vfs::mount_default(asset_dir)).is_err() {
+        bevy::log::error!("the filesystem is already initialized");
+    }
+}
+
+/// Returns the filesystem used by the game.
+pub fn get() -> &'static Vfs {
+    VFS.get_or_init(|| Vfs::mount_default(ASSET_DIR))
+}
+
+/// Reads a file from the filesystem used by the game.
+/// ## Errors
+/// Returns an error if the file can't be found or read.
+pub fn read<P: AsRef<Path>>(path: P) -> io::Result<Vec<u8>> {
+    get().read(path)
+}
+
+/// Reads a file from the filesystem used by the game as a string.
+/// ## Errors
+/// Returns an error if the file can't be found, read or isn't UTF-8.
+pub fn read_to_string<P: AsRef<Path>>(path: P) -> io::Result<String> {
+    get().read_to_string(path)
+}
+
+#[derive(Debug)]
+enum Mount {
+    Dir(PathBuf),
+    Opk(Opk),
+}
+
+/// A list of mounted archives and directories.
+#[derive(Debug, Default)]
+pub struct Vfs {
+    mounts: Vec<Mount>,
+}
+impl Vfs {
+    /// Mounts the archives in [PACK_DIR] sorted by name, and then `asset_dir`.
+    /// Archives which can't be opened are skipped.
+    pub fn mount_default<P: AsRef<Path>>(asset_dir: P) -> Self {
+        let mut vfs = Self::default();
+        let mut packs = std::fs::read_dir(PACK_DIR)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|e| e.path())
+            .filter(|p| p.extension().is_some_and(|e| e == PACK_EXTENSION))
+            .collect::<Vec<_>>();
+        packs.sort();
+        for pack in packs {
+            if let Err(e) = vfs.mount_opk(&pack) {
+                bevy::log::error!("failed to mount {pack:?}: {e}");
+            }
+        }
+        vfs.mount_dir(asset_dir);
+        vfs
+    }
+
+    /// Returns the paths of the mounted archives and directories, in mount order.
+    pub fn roots(&self) -> Vec<PathBuf> {
+        self.mounts
+            .iter()
+            .map(|mount| match mount {
+                Mount::Dir(dir) => dir.clone(),
+                Mount::Opk(opk) => opk.path().to_path_buf(),
+            })
+            .collect()
+    }
+
+    /// Returns where `path` is on disk in the last mounted directory,
+    /// which is where downloaded files are written.
+    pub fn disk_path<P: AsRef<Path>>(&self, path: P) -> Option<PathBuf> {
+        let name = normalize(path.as_ref())?;
+        self.mounts.iter().rev().find_map(|mount| match mount {
+            Mount::Dir(dir) => Some(dir.join(&name)),
+            Mount::Opk(_) => None,
+        })
+    }
+
+    /// Mounts a directory over the current mounts.
+    pub fn mount_dir<P: AsRef<Path>>(&mut self, path: P) {
+        self.mounts.push(Mount::Dir(path.as_ref().to_path_buf()));
+    }
+
+    /// Mounts an archive over the current mounts.
+    /// ## Errors
+    /// Returns an error if the archive can't be read.
+    pub fn mount_opk<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        self.mounts.push(Mount::Opk(Opk::open(path)?));
+        Ok(())
+    }
+
+    /// Reads a file from the last mount containing it.
+    /// ## Errors
+    /// Returns an error if the file can't be found or read.
+    pub fn read<P: AsRef<Path>>(&self, path: P) -> io::Result<Vec<u8>> {
+        let path = path.as_ref();
+        let name = normalize(path).ok_or_else(|| not_found(path))?;
+        for mount in self.mounts.iter().rev() {
+            match mount {
+                Mount::Dir(dir) => match std::fs::read(dir.join(&name)) {
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                    result => return result,
+                },
+                Mount::Opk(opk) if opk.contains(&name) => return opk.read(&name),
+                Mount::Opk(_) => continue,
+            }
+        }
+        Err(not_found(path))
+    }
+
+    /// Reads a file from the last mount containing it as a string.
+    /// ## Errors
+    /// Returns an error if the file can't be found, read or isn't UTF-8.
+    pub fn read_to_string<P: AsRef<Path>>(&self, path: P) -> io::Result<String> {
+        String::from_utf8(self.read(path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Returns true if any mount contains the file.
+    pub fn exists<P: AsRef<Path>>(&self, path: P) -> bool {
+        let Some(name) = normalize(path.as_ref()) else {
+            return false;
+        };
+        self.mounts.iter().any(|mount| match mount {
+            Mount::Dir(dir) => dir.join(&name).is_file(),
+            Mount::Opk(opk) => opk.contains(&name),
+        })
+    }
+
+    /// Returns true if any mount contains the directory.
+    pub fn is_dir<P: AsRef<Path>>(&self, path: P) -> bool {
+        let Some(name) = normalize(path.as_ref()) else {
+            return false;
+        };
+        let prefix = format!("{name}/");
+        self.mounts.iter().any(|mount| match mount {
+            Mount::Dir(dir) => dir.join(&name).is_dir(),
+            Mount::Opk(opk) => name.is_empty() || opk.names().any(|n| n.starts_with(&prefix)),
+        })
+    }
+
+    /// Lists the entries of a directory in all mounts, sorted by name.
+    /// Like [std::fs::read_dir] the entries are prefixed with `path`.
+    /// ## Errors
+    /// Returns an error if no mount contains the directory.
+    pub fn read_dir<P: AsRef<Path>>(&self, path: P) -> io::Result<Vec<PathBuf>> {
+        let path = path.as_ref();
+        if !self.is_dir(path) {
+            return Err(not_found(path));
+        }
+        let name = normalize(path).ok_or_else(|| not_found(path))?;
+        let prefix = if name.is_empty() {
+            name.clone()
+        } else {
+            format!("{name}/")
+        };
+
+        let mut entries = BTreeSet::new();
+        for mount in &self.mounts {
+            match mount {
+                Mount::Dir(dir) => {
+                    for entry in std::fs::read_dir(dir.join(&name)).into_iter().flatten() {
+                        entries.insert(entry?.file_name().to_string_lossy().into_owned());
+                    }
+                }
+                Mount::Opk(opk) => {
+                    for file in opk.names().filter_map(|n| n.strip_prefix(&prefix)) {
+                        entries.insert(file.split('/').next().unwrap_or(file).to_string());
+                    }
+                }
+            }
+        }
+        Ok(entries.into_iter().map(|e| path.join(e)).collect())
+    }
+}
+
+/// Turns a path into an archive file name, returns [None] for paths leaving the asset root.
+fn normalize(path: &Path) -> Option<String> {
+    let mut components = path.components().peekable();
+    if components
+        .peek()
+        .is_some_and(|c| c.as_os_str() == ASSET_DIR)
+    {
+        components.next();
+    }
+    let mut name = Vec::new();
+    for component in components {
+        match component {
+            Component::Normal(c) => name.push(c.to_string_lossy()),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    Some(name.join("/"))
+}
+
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("{path:?} not found"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_paths() {
+        assert_eq!(
+            normalize(Path::new("assets/maps/a.map")).unwrap(),
+            "maps/a.map"
+        );
+        assert_eq!(normalize(Path::new("./maps/a.map")).unwrap(), "maps/a.map");
+        assert_eq!(normalize(Path::new("assets")).unwrap(), "");
+        assert!(normalize(Path::new("../secret")).is_none());
+        assert!(normalize(Path::new("/etc/passwd")).is_none());
+    }
+
+    #[test]
+    fn overlay_mounts() {
+        use std::io::Write;
+
+        let dir = std::env::temp_dir().join(format!("vfs_test_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("maps")).unwrap();
+        std::fs::write(dir.join("maps/a.map"), "modded").unwrap();
+
+        let opk = dir.join("base.opk");
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(&opk).unwrap());
+        for (name, data) in [("maps/a.map", "base"), ("inputs.json", "{}")] {
+            zip.start_file(name, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            zip.write_all(data.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+
+        let mut vfs = Vfs::default();
+        vfs.mount_opk(&opk).unwrap();
+        assert_eq!(vfs.read_to_string("assets/maps/a.map").unwrap(), "base");
+        vfs.mount_dir(&dir);
+        assert_eq!(vfs.read_to_string("assets/maps/a.map").unwrap(), "modded");
+        assert_eq!(vfs.read_to_string("inputs.json").unwrap(), "{}");
+        assert!(vfs.read("maps/b.map").is_err());
+        assert!(vfs.is_dir("maps"));
+        assert_eq!(
+            vfs.read_dir("assets").unwrap(),
+            vec![
+                PathBuf::from("assets/base.opk"),
+                PathBuf::from("assets/inputs.json"),
+                PathBuf::from("assets/maps")
+            ]
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,75 @@
+//! Reading of `.opk` archives, which are plain zip files.
+
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use zip::{ZipArchive, result::ZipError};
+
+/// An opened archive, files are read from disk when requested.
+#[derive(Debug)]
+pub struct Opk {
+    path: PathBuf,
+    names: HashSet<String>,
+    archive: Mutex<ZipArchive<File>>,
+}
+impl Opk {
+    /// Reads the file list of the archive at `path`.
+    /// ## Errors
+    /// Returns an error if the file can't be read or is not a zip archive.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let archive = ZipArchive::new(File::open(&path)?).map_err(to_io)?;
+        let names = archive
+            .file_names()
+            .filter(|n| !n.ends_with('/'))
+            .map(str::to_string)
+            .collect();
+        Ok(Self {
+            path,
+            names,
+            archive: Mutex::new(archive),
+        })
+    }
+
+    /// Returns the archive this was opened from.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns true if the archive contains the file.
+    pub fn contains(&self, name: &str) -> bool {
+        self.names.contains(name)
+    }
+
+    /// Returns the names of all files in the archive.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.names.iter().map(String::as_str)
+    }
+
+    /// Reads a file from the archive.
+    /// ## Errors
+    /// Returns [io::ErrorKind::NotFound] if the file is not in the archive,
+    /// or an error if the archive can't be read.
+    pub fn read(&self, name: &str) -> io::Result<Vec<u8>> {
+        let mut archive = self
+            .archive
+            .lock()
+            .map_err(|_| io::Error::other("archive lock poisoned"))?;
+        let mut file = archive.by_name(name).map_err(to_io)?;
+        let mut data = Vec::with_capacity(file.size() as usize);
+        file.read_to_end(&mut data)?;
+        Ok(data)
+    }
+}
+
+fn to_io(e: ZipError) -> io::Error {
+    match e {
+        ZipError::Io(e) => e,
+        ZipError::FileNotFound => io::Error::new(io::ErrorKind::NotFound, e),
+        e => io::Error::new(io::ErrorKind::InvalidData, e),
+    }
+}
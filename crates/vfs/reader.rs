@@ -0,0 +1,53 @@
+//! Lets the Bevy asset server load assets through the [Vfs](crate::Vfs).
+
+use bevy::{
+    asset::io::{AssetReader, AssetReaderError, PathStream, Reader, VecReader},
+    tasks::futures_lite::stream,
+};
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+/// An [AssetReader] reading from [get](crate::get).
+#[derive(Debug, Default)]
+pub struct VfsAssetReader;
+
+fn read(path: &Path) -> Result<VecReader, AssetReaderError> {
+    match crate::read(path) {
+        Ok(data) => Ok(VecReader::new(data)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            Err(AssetReaderError::NotFound(path.to_path_buf()))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+impl AssetReader for VfsAssetReader {
+    async fn read<'a>(&'a self, path: &'a Path) -> Result<impl Reader + 'a, AssetReaderError> {
+        read(path)
+    }
+
+    async fn read_meta<'a>(&'a self, path: &'a Path) -> Result<impl Reader + 'a, AssetReaderError> {
+        let mut meta = path.as_os_str().to_owned();
+        meta.push(".meta");
+        read(&PathBuf::from(meta))
+    }
+
+    async fn read_directory<'a>(
+        &'a self,
+        path: &'a Path,
+    ) -> Result<Box<PathStream>, AssetReaderError> {
+        match crate::get().read_dir(path) {
+            Ok(entries) => Ok(Box::new(stream::iter(entries))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                Err(AssetReaderError::NotFound(path.to_path_buf()))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn is_directory<'a>(&'a self, path: &'a Path) -> Result<bool, AssetReaderError> {
+        Ok(crate::get().is_dir(path))
+    }
+}
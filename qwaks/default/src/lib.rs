@@ -1,17 +1,15 @@
 #![allow(missing_docs)]
 #![feature(thread_local)]
-use extism_pdk::Msgpack;
+use extism_pdk::{FromBytes, Msgpack, ToBytes};
 use faststr::FastStr;
 use qwak_helper_types::{
-    ControllerType, MapInteraction, PickupData, PlayerKilled, PlayerLeave, PlayerSpawnInfo,
-    Projectile, WeaponData,
+    CommandArg, CommandArgKind, CommandCall, CommandSignature, CommandValue, ControllerType,
+    HostError, HudAnchor, HudKind, HudStyle, HudWidget, MapInteraction, PickupData, PlayerKilled,
+    PlayerLeave, PlayerSpawnInfo, Projectile, QwakManifest, StorageScope, WeaponData,
 };
 use qwak_shared::QwakPlugin;
 use serde::{Deserialize, Serialize, de::DeserializeOwned};
-use std::{
-    any::type_name,
-    collections::{HashMap, HashSet},
-};
+use std::collections::HashMap;
 
 mod pickups;
 mod projectiles;
@@ -27,36 +25,110 @@ struct PlayerStats {
     deaths: usize,
 }
 
-fn storage_init() {
-    if let Ok(Some(keys)) = extism_pdk::var::get::<Msgpack<HashSet<String>>>("----Keys----") {
-        for k in keys.0 {
-            extism_pdk::var::remove(k).unwrap();
+/// The per map kills and deaths of the players in the lobby.
+const PLAYER_STATS: &str = "player_stats";
+/// The map time at which the round ends and the next map is loaded, set with `/settime`.
+const ROUND_END: &str = "round_end";
+/// The name of the HUD timer showing the time left of the round.
+const ROUND_TIMER: &str = "round_timer";
+
+/// Returns the value stored under `key`.
+fn storage_get<T: DeserializeOwned>(scope: StorageScope, key: &str) -> Option<T> {
+    let bytes = game::storage::get(scope, key.to_string())?;
+    Msgpack::<T>::from_bytes(&bytes).ok().map(|v| v.0)
+}
+/// Stores `val` under `key`.
+fn storage_set<T: Serialize>(scope: StorageScope, key: &str, val: T) {
+    game::storage::set(scope, key.to_string(), Msgpack(val).to_bytes().ok());
+}
+/// Changes the value stored under `key` with `f`, again if another plugin changed it meanwhile.
+fn storage_update<T: Serialize + DeserializeOwned + Default>(
+    scope: StorageScope,
+    key: &str,
+    mut f: impl FnMut(&mut T),
+) {
+    loop {
+        let old = game::storage::get(scope, key.to_string());
+        let mut val = old
+            .as_deref()
+            .and_then(|b| Msgpack::<T>::from_bytes(b).ok())
+            .map(|v| v.0)
+            .unwrap_or_default();
+        f(&mut val);
+        let new = Msgpack(val).to_bytes().ok();
+        if game::storage::compare_and_swap(scope, key.to_string(), old, new) {
+            return;
         }
     }
-
-    extism_pdk::var::set("----Keys----", Msgpack(HashSet::<String>::new())).unwrap();
 }
-fn storage_set<T: Serialize>(val: T) {
-    let mut keys: HashSet<String> = extism_pdk::var::get("----Keys----")
-        .unwrap()
-        .map(|p: Msgpack<HashSet<String>>| p.0)
-        .unwrap_or_default();
-    keys.insert(type_name::<T>().to_string());
-    extism_pdk::var::set("----Keys----", Msgpack(keys)).unwrap();
-    extism_pdk::var::set(type_name::<T>(), Msgpack(val)).unwrap();
+
+/// The name of a player, or "unknown player" if the game does not know them.
+fn player_name(id: u64) -> String {
+    game::player::get_name(id).unwrap_or_else(|_| "unknown player".to_string())
 }
-fn storage_get<T: DeserializeOwned>() -> Option<T> {
-    extism_pdk::var::get::<Msgpack<T>>(type_name::<T>())
-        .ok()
-        .and_then(|o| o)
-        .map(|o| o.0)
+/// Logs a failed host call made by `script`.
+fn log_failure(script: &str, res: Result<(), HostError>) {
+    if let Err(e) = res {
+        log::error(format!("{script}: {e}"));
+    }
 }
 
 // Simple QWAK plugin that contains the required functions.
 // This is compiled to WASM.
 struct Plugin;
 impl QwakPlugin for Plugin {
-    fn plugin_init() {}
+    fn plugin_init() {
+        game::command::register(CommandSignature {
+            name: "givegun".to_string(),
+            description: "Drops a weapon at a player.".to_string(),
+            args: vec![
+                CommandArg {
+                    name: "weapon".to_string(),
+                    kind: CommandArgKind::String,
+                    optional: false,
+                },
+                CommandArg {
+                    name: "player".to_string(),
+                    kind: CommandArgKind::Player,
+                    optional: true,
+                },
+            ],
+            admin: true,
+        });
+        game::command::register(CommandSignature {
+            name: "ready".to_string(),
+            description: "Tells everyone you are ready.".to_string(),
+            args: Vec::new(),
+            admin: false,
+        });
+        game::command::register(CommandSignature {
+            name: "settime".to_string(),
+            description: "Ends the round after some seconds, 0 lets it go on forever.".to_string(),
+            args: vec![CommandArg {
+                name: "seconds".to_string(),
+                kind: CommandArgKind::Float,
+                optional: false,
+            }],
+            admin: true,
+        });
+        game::command::register(CommandSignature {
+            name: "map".to_string(),
+            description: "Changes to a map, or the next one in the rotation.".to_string(),
+            args: vec![CommandArg {
+                name: "name".to_string(),
+                kind: CommandArgKind::String,
+                optional: true,
+            }],
+            admin: true,
+        });
+    }
+
+    // Everything is in the host storage, which is kept when the plugin is reloaded
+    fn plugin_save_state() -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn plugin_restore_state(_state: Vec<u8>) {}
 
     fn player_info() -> PlayerSpawnInfo {
         PlayerSpawnInfo {
@@ -64,12 +136,19 @@ impl QwakPlugin for Plugin {
         }
     }
 
-    fn plugin_name() -> String {
-        "Ondth".to_string()
-    }
-
-    fn plugin_version() -> [i32; 3] {
-        [0, 0, 1]
+    fn plugin_manifest() -> QwakManifest {
+        QwakManifest {
+            name: "Ondth".to_string(),
+            version: [0, 0, 1],
+            host_api: qwak_shared::HOST_API_VERSION,
+            capabilities: [
+                "audio", "brush", "command", "entity", "hud", "map", "message", "player", "storage",
+                "tick",
+            ]
+            .map(String::from)
+            .to_vec(),
+            dependencies: Vec::new(),
+        }
     }
 
     fn plugin_get_projectiles() -> HashMap<FastStr, Projectile> {
@@ -95,7 +174,7 @@ impl QwakPlugin for Plugin {
     ) {
         match &*script {
             "debug_log" => {
-                let name = game::player::get_name(player_id);
+                let name = player_name(player_id);
                 game::broadcast_message(format!("{name}: script: {script:?}, target: {target:?}"))
             }
             "translate_brush" => {
@@ -112,20 +191,25 @@ impl QwakPlugin for Plugin {
                 } else {
                     ([0.0, 0.1, 0.0], 100)
                 };
-                game::brush::rotate(target, x, y, z, delay);
+                log_failure(&script, game::brush::rotate(target, x, y, z, delay));
                 // host::brush_translate(target, x, y, z, delay);
             }
             "open_big_doors" => {
-                #[derive(Default, Deserialize, Serialize)]
-                struct BoolDoor(bool);
-                if storage_get::<BoolDoor>().unwrap_or_default().0 {
+                if storage_get(StorageScope::Map, "big_doors_open").unwrap_or(false) {
+                    return;
+                }
+                let open = || -> Result<(), HostError> {
+                    game::brush::rotate("bigDoor1".to_string(), 0.0, 50.0, 0.0, 100000)?;
+                    game::brush::translate("bigDoor1".to_string(), 0.5, 0.0, -0.5, 100000)?;
+                    game::brush::rotate("bigDoor2".to_string(), 0.0, -50.0, 0.0, 100000)?;
+                    game::brush::translate("bigDoor2".to_string(), 0.5, 0.0, 0.5, 100000)
+                };
+                // A map without the doors has nothing to open
+                if let Err(e) = open() {
+                    log::error(format!("{script}: {e}"));
                     return;
                 }
-                game::brush::rotate("bigDoor1".to_string(), 0.0, 50.0, 0.0, 100000);
-                game::brush::translate("bigDoor1".to_string(), 0.5, 0.0, -0.5, 100000);
-                game::brush::rotate("bigDoor2".to_string(), 0.0, -50.0, 0.0, 100000);
-                game::brush::translate("bigDoor2".to_string(), 0.5, 0.0, 0.5, 100000);
-                storage_set(BoolDoor(true));
+                storage_set(StorageScope::Map, "big_doors_open", true);
                 for i in 0..4 {
                     game::map::timeout(
                         MapInteraction {
@@ -153,96 +237,183 @@ impl QwakPlugin for Plugin {
                 game::audio::global::play(sound, volume);
             }
             "elevator" => {
-                #[derive(Default, Deserialize, Serialize)]
-                struct FlipFlop(bool);
-                let k = storage_get::<FlipFlop>().unwrap_or_default().0;
+                let k = storage_get(StorageScope::Map, "elevator_up").unwrap_or(false);
                 let target = "elevator".to_string();
-                if k {
-                    game::brush::translate(target, 0.0, -2.0, 0.0, 60000);
-                    game::broadcast_message("going down".to_string());
-                } else {
-                    game::brush::translate(target, 0.0, 2.0, 0.0, 60000);
-                    game::broadcast_message("going up".to_string());
+                let (y, message) = match k {
+                    true => (-2.0, "going down"),
+                    false => (2.0, "going up"),
+                };
+                if let Err(e) = game::brush::translate(target, 0.0, y, 0.0, 60000) {
+                    log::error(format!("{script}: {e}"));
+                    return;
                 }
-                storage_set(FlipFlop(!k));
+                game::broadcast_message(message.to_string());
+                storage_set(StorageScope::Map, "elevator_up", !k);
             }
             "hurt_me" => {
                 game::broadcast_message("OUCH!".to_string());
-                game::player::hurt(player_id, 10.0);
+                log_failure(&script, game::player::hurt(player_id, 10.0));
             }
             "heal_me" => {
                 game::broadcast_message("DE-OUCH!".to_string());
-                game::player::heal(player_id, 10.0);
+                log_failure(&script, game::player::heal(player_id, 10.0));
+            }
+            _ => log::error(format!("unknown interaction: {script}")),
+        }
+    }
+
+    fn plugin_command(
+        CommandCall {
+            player_id,
+            command,
+            args,
+        }: CommandCall,
+    ) {
+        match (&*command, &args[..]) {
+            ("givegun", [CommandValue::String(weapon), rest @ ..]) => {
+                let target = match rest {
+                    [CommandValue::Player(id)] => *id,
+                    _ => player_id,
+                };
+                let give = || -> Result<(), HostError> {
+                    let position = game::player::info(target)?.position;
+                    game::entity::spawn_pickup(weapon.clone(), position)?;
+                    Ok(())
+                };
+                if let Err(e) = give() {
+                    log_failure(&command, game::player::message(player_id, e.to_string()));
+                }
+            }
+            ("ready", []) => {
+                game::broadcast_message(format!("{} is ready", player_name(player_id)));
+            }
+            ("settime", [CommandValue::Float(seconds)]) => {
+                if *seconds <= 0.0 {
+                    game::storage::set(StorageScope::Map, ROUND_END.to_string(), None);
+                    log_failure(&command, game::hud::remove(None, ROUND_TIMER.to_string()));
+                    return;
+                }
+                storage_set(StorageScope::Map, ROUND_END, game::map::time() + seconds);
+                let timer = HudWidget {
+                    kind: HudKind::Timer { seconds: *seconds },
+                    anchor: HudAnchor::TopRight,
+                    style: HudStyle::default(),
+                };
+                log_failure(&command, game::hud::set(None, ROUND_TIMER.to_string(), timer));
+            }
+            ("map", rest) => {
+                // A plain name is a map in the module's maps folder
+                let path = match rest {
+                    [CommandValue::String(name)] if !name.contains('/') => {
+                        format!("assets/maps/{name}.map")
+                    }
+                    [CommandValue::String(path)] => path.clone(),
+                    _ => String::new(),
+                };
+                if let Err(e) = game::map::change(path) {
+                    log_failure(&command, game::player::message(player_id, e.to_string()));
+                }
             }
-            _ => panic!("unknown interaction: {script}"),
+            _ => {}
         }
     }
 
     fn map_get_lobby_info() -> String {
-        let player_info = storage_get::<HashMap<u64, PlayerStats>>().unwrap_or_default();
+        let player_info: HashMap<u64, PlayerStats> =
+            storage_get(StorageScope::Map, PLAYER_STATS).unwrap_or_default();
         let mut s = "lobby info:".to_string();
         for (p, v) in player_info.iter() {
-            s += &format!(
-                "\n{}: d: {}, k: {}",
-                game::player::get_name(*p).to_lowercase(),
-                v.deaths,
-                v.kills
-            );
+            let name = player_name(*p).to_lowercase();
+            let total: u64 =
+                storage_get(StorageScope::Persistent, &format!("frags/{name}")).unwrap_or_default();
+            s += &format!("\n{name}: d: {}, k: {} (total {total})", v.deaths, v.kills);
         }
         s
     }
 
     fn map_init() {
-        log::debug("clearing map storage...".to_string());
-        storage_init();
-        let mut player_info = storage_get::<HashMap<u64, PlayerStats>>().unwrap_or_default();
-        player_info.clear();
-        player_info.insert(game::host_id(), PlayerStats::default());
-        storage_set(player_info);
+        // The map storage starts out empty on every map, the lobby is kept through map changes
+        let player_info = game::player::list()
+            .into_iter()
+            .chain([game::host_id()])
+            .map(|id| (id, PlayerStats::default()))
+            .collect::<HashMap<_, _>>();
+        storage_set(StorageScope::Map, PLAYER_STATS, player_info);
+    }
+
+    fn map_tick(_dt: f32) {
+        let Some(end) = storage_get::<f32>(StorageScope::Map, ROUND_END) else {
+            return;
+        };
+        if game::map::time() < end {
+            return;
+        }
+        // Removed first so the round only ends once while the map changes
+        game::storage::set(StorageScope::Map, ROUND_END.to_string(), None);
+        game::broadcast_message("TIME IS UP!".to_string());
+        log_failure("round_end", game::map::change(String::new()));
     }
 
     fn map_player_killed(PlayerKilled { player_id, by_id }: PlayerKilled) {
-        let killed = game::player::get_name(player_id);
-        let killer = game::player::get_name(by_id.unwrap_or_default());
+        let killed = player_name(player_id);
+        let killer = player_name(by_id.unwrap_or_default());
         game::broadcast_message(format!(
             "{} GOT FRAGGED BY {}!",
             killed.to_lowercase(),
             killer.to_uppercase()
         ));
 
-        let mut player_info = storage_get::<HashMap<u64, PlayerStats>>().unwrap_or_default();
-        player_info.entry(player_id).or_default().deaths += 1;
-        player_info
-            .entry(by_id.unwrap_or_default())
-            .or_default()
-            .kills += 1;
-        storage_set(player_info);
+        storage_update(
+            StorageScope::Map,
+            PLAYER_STATS,
+            |player_info: &mut HashMap<u64, PlayerStats>| {
+                player_info.entry(player_id).or_default().deaths += 1;
+                player_info
+                    .entry(by_id.unwrap_or_default())
+                    .or_default()
+                    .kills += 1;
+            },
+        );
+        if by_id.is_some_and(|id| id != player_id) {
+            let key = format!("frags/{}", killer.to_lowercase());
+            storage_update(StorageScope::Persistent, &key, |frags: &mut u64| {
+                *frags += 1
+            });
+        }
     }
     fn map_player_respawn(PlayerKilled { player_id, .. }: PlayerKilled) {
         let spawn = game::map::spawn_point();
-        game::player::set_stats(player_id, 100.0, 0.0);
-        game::player::teleport(player_id, spawn.x, spawn.y, spawn.z);
+        let respawn = || -> Result<(), HostError> {
+            game::player::set_stats(player_id, 100.0, 0.0)?;
+            game::player::teleport(player_id, spawn.x, spawn.y, spawn.z)
+        };
+        log_failure("respawn", respawn());
     }
 
     fn map_player_join(id: u64) {
-        game::broadcast_message(format!(
-            "{} JOINED",
-            game::player::get_name(id).to_lowercase()
-        ));
+        game::broadcast_message(format!("{} JOINED", player_name(id).to_lowercase()));
 
-        let mut player_info = storage_get::<HashMap<u64, PlayerStats>>().unwrap_or_default();
-        player_info.insert(id, PlayerStats::default());
-        storage_set(player_info);
+        storage_update(
+            StorageScope::Map,
+            PLAYER_STATS,
+            |player_info: &mut HashMap<u64, PlayerStats>| {
+                player_info.insert(id, PlayerStats::default());
+            },
+        );
     }
 
     fn map_player_leave(PlayerLeave { id, reason }: PlayerLeave) {
         game::broadcast_message(format!(
             "{} LEFT ({reason})",
-            game::player::get_name(id).to_lowercase()
+            player_name(id).to_lowercase()
         ));
 
-        let mut player_info = storage_get::<HashMap<u64, PlayerStats>>().unwrap_or_default();
-        player_info.remove(&id);
-        storage_set(player_info);
+        storage_update(
+            StorageScope::Map,
+            PLAYER_STATS,
+            |player_info: &mut HashMap<u64, PlayerStats>| {
+                player_info.remove(&id);
+            },
+        );
     }
 }
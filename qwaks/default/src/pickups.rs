@@ -95,6 +95,79 @@ pub fn get_pickups() -> HashMap<FastStr, PickupData> {
             texture_file: "textures/weapons/WeaponMegaTexture.png".into(),
             scale: 0.01,
         }),
+        ("ammo_bullet".into(), PickupData {
+            pickup_type: PickupType::Ammo { amount: 20, max: 200 },
+            classname: "ammo_bullet".into(),
+            gives: "bullets".into(),
+            pickup_model: "models/Pickups/Ammo/Bullet.obj".into(),
+            pickup_material: "models/Pickups/Ammo/Bullets.mtl".into(),
+            texture_file: "textures/weapons/WeaponMegaTexture.png".into(),
+            scale: 0.01,
+        }),
+        ("ammo_shell".into(), PickupData {
+            pickup_type: PickupType::Ammo { amount: 8, max: 50 },
+            classname: "ammo_shell".into(),
+            gives: "shells".into(),
+            pickup_model: "models/Pickups/Ammo/Shell.obj".into(),
+            pickup_material: "models/Pickups/Ammo/Shells.mtl".into(),
+            texture_file: "textures/weapons/WeaponMegaTexture.png".into(),
+            scale: 0.01,
+        }),
+        ("ammo_rocket".into(), PickupData {
+            pickup_type: PickupType::Ammo { amount: 4, max: 30 },
+            classname: "ammo_rocket".into(),
+            gives: "rockets".into(),
+            pickup_model: "models/Pickups/Ammo/Rocket.obj".into(),
+            pickup_material: "models/Pickups/Ammo/Rockets.mtl".into(),
+            texture_file: "textures/weapons/WeaponMegaTexture.png".into(),
+            scale: 0.01,
+        }),
+        ("ammo_fuel".into(), PickupData {
+            pickup_type: PickupType::Ammo { amount: 50, max: 200 },
+            classname: "ammo_fuel".into(),
+            gives: "fuel".into(),
+            pickup_model: "models/Pickups/Ammo/Fuel.obj".into(),
+            pickup_material: "models/Pickups/Ammo/Fuel.mtl".into(),
+            texture_file: "textures/weapons/WeaponMegaTexture.png".into(),
+            scale: 0.01,
+        }),
+        ("ammo_energy".into(), PickupData {
+            pickup_type: PickupType::Ammo { amount: 30, max: 150 },
+            classname: "ammo_energy".into(),
+            gives: "energy".into(),
+            pickup_model: "models/Pickups/Ammo/Energy.obj".into(),
+            pickup_material: "models/Pickups/Ammo/EnergyCell.mtl".into(),
+            texture_file: "textures/weapons/WeaponMegaTexture.png".into(),
+            scale: 0.01,
+        }),
+        ("ammo_nuke".into(), PickupData {
+            pickup_type: PickupType::Ammo { amount: 1, max: 5 },
+            classname: "ammo_nuke".into(),
+            gives: "nukes".into(),
+            pickup_model: "models/Pickups/Ammo/Nuke.obj".into(),
+            pickup_material: "models/Pickups/Ammo/Nuke.mtl".into(),
+            texture_file: "textures/weapons/WeaponMegaTexture.png".into(),
+            scale: 0.01,
+        }),
+        // Health and armor have no models of their own yet, they borrow the ammo ones
+        ("item_health".into(), PickupData {
+            pickup_type: PickupType::Health { amount: 25.0, max: 100.0 },
+            classname: "item_health".into(),
+            gives: "health".into(),
+            pickup_model: "models/Pickups/Ammo/Fuel.obj".into(),
+            pickup_material: "models/Pickups/Ammo/Fuel.mtl".into(),
+            texture_file: "textures/weapons/WeaponMegaTexture.png".into(),
+            scale: 0.01,
+        }),
+        ("item_armor".into(), PickupData {
+            pickup_type: PickupType::Armor { amount: 50.0, max: 100.0 },
+            classname: "item_armor".into(),
+            gives: "armor".into(),
+            pickup_model: "models/Pickups/Ammo/Energy.obj".into(),
+            pickup_material: "models/Pickups/Ammo/EnergyCell.mtl".into(),
+            texture_file: "textures/weapons/WeaponMegaTexture.png".into(),
+            scale: 0.01,
+        }),
     ]
     .into()
 }
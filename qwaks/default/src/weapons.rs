@@ -1,5 +1,5 @@
 use faststr::FastStr;
-use qwak_helper_types::{Attack, SoundEffect, WeaponAnimations, WeaponData};
+use qwak_helper_types::{Attack, SoundEffect, WeaponAmmo, WeaponAnimations, WeaponData};
 use std::collections::HashMap;
 
 pub fn get_weapons() -> HashMap<FastStr, WeaponData> {
@@ -32,6 +32,15 @@ pub fn get_weapons() -> HashMap<FastStr, WeaponData> {
             attack2: Attack::Projectile {
                 projectile: "nuke".into(),
             },
+            ammo: Some(WeaponAmmo {
+                ammo_type: "nukes".into(),
+                magazine: 0,
+                per_shot1: 1,
+                per_shot2: 1,
+                given: 1,
+                reload_time: 1.0,
+                auto_reload: true,
+            }),
             pickup_message1: "PICKED UP: ".into(),
             pickup_message2: "! FOR INDOOR USAGE ONLY!".into(),
             fancy_name: "sun slinger".into(),
@@ -72,6 +81,15 @@ pub fn get_weapons() -> HashMap<FastStr, WeaponData> {
                 damage_mod: 5.0,
                 range: 100.0,
             },
+            ammo: Some(WeaponAmmo {
+                ammo_type: "bullets".into(),
+                magazine: 6,
+                per_shot1: 1,
+                per_shot2: 1,
+                given: 18,
+                reload_time: 1.5,
+                auto_reload: true,
+            }),
             pickup_message1: "PICKED UP: ".into(),
             pickup_message2: "!".into(),
             fancy_name: "revolver".into(),
@@ -112,6 +130,15 @@ pub fn get_weapons() -> HashMap<FastStr, WeaponData> {
                 damage_mod: 5.0,
                 range: 100.0,
             },
+            ammo: Some(WeaponAmmo {
+                ammo_type: "shells".into(),
+                magazine: 0,
+                per_shot1: 1,
+                per_shot2: 1,
+                given: 8,
+                reload_time: 1.0,
+                auto_reload: true,
+            }),
             pickup_message1: "PICKED UP: ".into(),
             pickup_message2: "!".into(),
             fancy_name: "pump shotgun".into(),
@@ -140,6 +167,7 @@ pub fn get_weapons() -> HashMap<FastStr, WeaponData> {
             pickup_sound: None,
             attack1: Attack::None,
             attack2: Attack::None,
+            ammo: None,
             pickup_message1: "PICKED UP: ".into(),
             pickup_message2: "! SEEMS LIKE A BAD IDEA...".into(),
             fancy_name: "bundle o' dynamite".into(),
@@ -168,6 +196,7 @@ pub fn get_weapons() -> HashMap<FastStr, WeaponData> {
             pickup_sound: None,
             attack1: Attack::None,
             attack2: Attack::None,
+            ammo: None,
             pickup_message1: "PICKED UP: ".into(),
             pickup_message2: "! KEEP AWAY FROM TUPPERWARE!".into(),
             fancy_name: "flamethrower".into(),
@@ -208,6 +237,15 @@ pub fn get_weapons() -> HashMap<FastStr, WeaponData> {
                 damage_mod: 2.0,
                 range: 100.0,
             },
+            ammo: Some(WeaponAmmo {
+                ammo_type: "bullets".into(),
+                magazine: 40,
+                per_shot1: 1,
+                per_shot2: 1,
+                given: 40,
+                reload_time: 2.0,
+                auto_reload: true,
+            }),
             pickup_message1: "PICKED UP: ".into(),
             pickup_message2: "! PEW PEW!".into(),
             fancy_name: "belter".into(),
@@ -236,6 +274,7 @@ pub fn get_weapons() -> HashMap<FastStr, WeaponData> {
             pickup_sound: None,
             attack1: Attack::None,
             attack2: Attack::None,
+            ammo: None,
             pickup_message1: "PICKED UP: ".into(),
             pickup_message2: "!".into(),
             fancy_name: "UNNAMNED_WEAPON".into(),
@@ -290,6 +329,7 @@ pub fn get_weapons() -> HashMap<FastStr, WeaponData> {
                 damage_mod: 2.0,
                 range: 1.3,
             },
+            ammo: None,
             pickup_message1: "PICKED UP: ".into(),
             pickup_message2: "! SLICE AND DICE!".into(),
             fancy_name: "bayonet".into(),
@@ -332,6 +372,15 @@ pub fn get_weapons() -> HashMap<FastStr, WeaponData> {
                 damage_mod: 5.0,
                 range: 100.0,
             },
+            ammo: Some(WeaponAmmo {
+                ammo_type: "shells".into(),
+                magazine: 0,
+                per_shot1: 2,
+                per_shot2: 2,
+                given: 8,
+                reload_time: 1.0,
+                auto_reload: true,
+            }),
             pickup_message1: "PICKED UP: ".into(),
             pickup_message2: "! GROOVY!".into(),
             fancy_name: "super shotgun".into(),
@@ -364,6 +413,15 @@ pub fn get_weapons() -> HashMap<FastStr, WeaponData> {
             attack2: Attack::Projectile {
                 projectile: "rocket".into(),
             },
+            ammo: Some(WeaponAmmo {
+                ammo_type: "rockets".into(),
+                magazine: 0,
+                per_shot1: 1,
+                per_shot2: 1,
+                given: 4,
+                reload_time: 1.0,
+                auto_reload: true,
+            }),
             pickup_message1: "PICKED UP: ".into(),
             pickup_message2: "! STAY BACK!".into(),
             fancy_name: "rpg".into(),
@@ -6,7 +6,7 @@ use std::collections::HashMap;
 use faststr::FastStr;
 use qwak_helper_types::{
     ControllerType, MapInteraction, PickupData, PlayerKilled, PlayerLeave, PlayerSpawnInfo,
-    Projectile, WeaponData,
+    Projectile, QwakManifest, WeaponData,
 };
 use qwak_shared::QwakPlugin;
 qwak_shared::plugin_gen!(Plugin);
@@ -18,18 +18,26 @@ struct Plugin;
 impl QwakPlugin for Plugin {
     fn plugin_init() {}
 
+    fn plugin_save_state() -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn plugin_restore_state(_: Vec<u8>) {}
+
     fn player_info() -> PlayerSpawnInfo {
         PlayerSpawnInfo {
             controller_type: ControllerType::D3D,
         }
     }
 
-    fn plugin_name() -> String {
-        "Void".to_string()
-    }
-
-    fn plugin_version() -> [i32; 3] {
-        [0, 0, 1]
+    fn plugin_manifest() -> QwakManifest {
+        QwakManifest {
+            name: "Void".to_string(),
+            version: [0, 0, 1],
+            host_api: qwak_shared::HOST_API_VERSION,
+            capabilities: Vec::new(),
+            dependencies: Vec::new(),
+        }
     }
 
     fn map_init() {}
@@ -50,12 +50,10 @@ else
     echo -e "    ${bold}${red}Error:${normal} compiling \"$proj\" failed!" 
     exit 1
 fi
-rm -f assets
-ln -s "qwaks/$proj/assets/" assets
 echo -e "    ${bold}${orange}Compiled${normal} \"$proj\" QWAK file to: \"target/wasm32-unknown-unknown/${wasmOut}/$proj.wasm\"" 
-mkdir -p assets/qwaks/
-cp "target/wasm32-unknown-unknown/${wasmOut}/$proj.wasm" "assets/qwaks/default.wasm"
+mkdir -p "qwaks/$proj/assets/qwaks/"
+cp "target/wasm32-unknown-unknown/${wasmOut}/$proj.wasm" "qwaks/$proj/assets/qwaks/default.wasm"
 echo -e "      ${bold}${orange}Copied${normal} \"$proj\" QWAK file to asset directory" 
 echo -e "   ${bold}${orange}Compiling${normal} \"$proj\" with flags: $features" 
 
-cargo $optFlag --no-default-features $features --
\ No newline at end of file
+cargo $optFlag --no-default-features $features -- --qwak "$proj"
\ No newline at end of file
@@ -1,14 +1,20 @@
-use crate::particles::ParticleMap;
+use crate::{map_gen::GameObject, particles::ParticleMap};
 use bevy::{
-    asset::AssetServer,
+    asset::{AssetServer, Assets},
     ecs::{
         component::Component,
+        entity::Entity,
         schedule::{IntoSystemConfigs, SystemConfigs},
         system::{Commands, Query},
     },
+    gltf::GltfAssetLabel,
     math::Vec3,
+    pbr::{MeshMaterial3d, StandardMaterial},
+    prelude::{Mesh3d, default},
+    scene::SceneRoot,
     transform::components::Transform,
 };
+use faststr::FastStr;
 use qwak_helper_types::Projectile;
 
 pub mod message;
@@ -31,6 +37,82 @@ impl ProjectileEntity {
     }
 
     pub fn collision(_query: Query<&ProjectileEntity>) {}
+
+    /// Spawns `proj` at `origin`, flying towards `dir`.
+    pub fn spawn(
+        commands: &mut Commands,
+        asset_server: &AssetServer,
+        materials: &mut Assets<StandardMaterial>,
+        proj: &Projectile,
+        origin: Vec3,
+        dir: Vec3,
+    ) -> Entity {
+        // Fix mesh rotation
+        let mut trans = Transform::from_translation(origin);
+        trans.scale = Vec3::splat(proj.scale);
+        trans.look_at(origin + dir, Vec3::Y);
+        trans.rotate_x(proj.rotation[0].to_radians());
+        trans.rotate_y(proj.rotation[1].to_radians());
+        trans.rotate_z(proj.rotation[2].to_radians());
+
+        commands
+            .spawn((
+                Mesh3d(asset_server.load(&*proj.model_file)),
+                MeshMaterial3d(materials.add(StandardMaterial {
+                    base_color_texture: Some(asset_server.load(&*proj.texture_file)),
+                    perceptual_roughness: 1.0,
+                    reflectance: 0.0,
+                    ..default()
+                })),
+                trans,
+                GameObject,
+            ))
+            .insert(ProjectileEntity {
+                data: proj.clone(),
+                dir,
+            })
+            .id()
+    }
+}
+
+/// An entity spawned by a plugin, with the id the plugin knows it by.
+#[derive(Debug, Component)]
+pub struct PluginEntity(pub u64);
+
+/// A model placed in the world by a plugin.
+#[derive(Debug, Component)]
+pub struct PropEntity {
+    pub model: FastStr,
+    /// Props do not move, so this is where it is.
+    pub translation: Vec3,
+    pub scale: f32,
+}
+impl PropEntity {
+    /// Spawns the first scene of the GLTF `model` at `pos`.
+    pub fn spawn(
+        commands: &mut Commands,
+        asset_server: &AssetServer,
+        model: FastStr,
+        pos: Vec3,
+        scale: f32,
+    ) -> Entity {
+        let mut trans = Transform::from_translation(pos);
+        trans.scale = Vec3::splat(scale);
+        commands
+            .spawn((
+                SceneRoot(
+                    asset_server.load(GltfAssetLabel::Scene(0).from_asset(model.to_string())),
+                ),
+                trans,
+                GameObject,
+            ))
+            .insert(PropEntity {
+                model,
+                translation: pos,
+                scale,
+            })
+            .id()
+    }
 }
 
 pub fn hitscan_hit_gfx(
@@ -29,20 +29,22 @@ impl PickupEntity {
     pub fn handle_pickups(
         mut commands: Commands,
         pickups: Query<&PickupEntity>,
-        mut players: Query<&mut Player>,
+        players: Query<&Player>,
         mut reader: EventReader<CollisionEvent>,
         mut server_event: EventWriter<SimulationEvent>,
     ) {
         for event in reader.read() {
             if let CollisionEvent::Started(ent_pickup, player, CollisionEventFlags::SENSOR) = event
             {
-                if let (Ok(player), Ok(pickup)) =
-                    (players.get_mut(*player), pickups.get(*ent_pickup))
-                {
+                if let (Ok(player), Ok(pickup)) = (players.get(*player), pickups.get(*ent_pickup)) {
+                    // Refused pickups stay until the player touches them again
+                    if !player.can_pickup(&pickup.data.pickup_type, &pickup.data.gives) {
+                        continue;
+                    }
                     server_event.send(SimulationEvent::PlayerPicksUpPickup {
                         id: pickup.id,
                         player: player.id,
-                        pickup: pickup.data.gives.clone(),
+                        classname: pickup.data.classname.clone(),
                     });
 
                     commands.entity(*ent_pickup).despawn();
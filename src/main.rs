@@ -6,8 +6,12 @@ use crate::net::{
     steam::{SteamClient, try_steam},
 };
 use bevy::{
-    core_pipeline::experimental::taa::TemporalAntiAliasPlugin, image::ImageAddressMode,
-    log::LogPlugin, pbr::PointLightShadowMap, prelude::*,
+    asset::io::{AssetSource, AssetSourceId},
+    core_pipeline::experimental::taa::TemporalAntiAliasPlugin,
+    image::ImageAddressMode,
+    log::LogPlugin,
+    pbr::PointLightShadowMap,
+    prelude::*,
 };
 use bevy_atmosphere::plugin::AtmospherePlugin;
 use bevy_hanabi::HanabiPlugin;
@@ -26,7 +30,9 @@ use bevy_scene_hook::reload::Plugin as HookPlugin;
 use bevy_simple_text_input::TextInputPlugin;
 use net::{ClientMessage, Connections, ServerMessage};
 use plugins::{ClientPlugin, GameStage, MainMenuStage, Resources, ServerPlugin, StartupStage};
+use qwak_modules::{LaunchArgs, QwakModules};
 use steamworks::{AppId, SingleClient};
+use vfs::VfsAssetReader;
 
 mod entities;
 mod mainmenu;
@@ -37,6 +43,9 @@ mod player;
 mod plugins;
 mod queries;
 mod qwak_host_functions;
+mod qwak_modules;
+mod qwak_storage;
+mod qwaks;
 mod startup;
 mod ui;
 
@@ -47,9 +56,12 @@ fn steam_callbacks(client: NonSend<SingleClient>) {
 }
 
 fn main() {
-    info!("running with asset hash: {}", integrity::get_asset_hash());
-
     let mut app = App::new();
+    // Read assets through the packs as well as the asset directory
+    app.register_asset_source(
+        AssetSourceId::Default,
+        AssetSource::build().with_reader(|| Box::new(VfsAssetReader)),
+    );
     app.add_plugins(
         DefaultPlugins
             .set({
@@ -77,6 +89,19 @@ fn main() {
 
     // app.add_plugins(bevy_inspector_egui::quick::WorldInspectorPlugin::new());
 
+    let args = LaunchArgs::parse();
+    let modules = QwakModules::discover(args.qwak.as_deref());
+    if args.list_qwaks {
+        #[allow(clippy::print_stdout)]
+        for module in &modules.installed {
+            println!("{}\t{}", module.name, module.asset_dir.display());
+        }
+        return;
+    }
+    // Must happen before anything reads an asset
+    vfs::init(&modules.active.asset_dir);
+    app.insert_resource(args).insert_resource(modules);
+
     app.add_event::<ClientMessage>()
         .add_event::<SimulationEvent>()
         .add_event::<ServerMessage>()
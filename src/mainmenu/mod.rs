@@ -1,7 +1,8 @@
 use crate::{
     APP_ID,
-    net::{self, NetState, steam::SteamClient},
-    plugins::Qwaks,
+    net::{self, DisconnectReason, NetState, steam::SteamClient},
+    qwak_modules::{LaunchArgs, QwakModules},
+    qwaks::Qwaks,
     ui::menu_button::MenuButton,
 };
 use bevy::{ecs::system::SystemState, prelude::*};
@@ -9,7 +10,7 @@ use bevy_simple_text_input::{TextInput, TextInputSettings, TextInputTextFont, Te
 use macros::{error_continue, error_return};
 use resources::{CurrentMap, CurrentStage};
 use std::{
-    fs, io,
+    io,
     path::{Path, PathBuf},
 };
 use steamworks::FriendFlags;
@@ -22,6 +23,7 @@ struct MainMenuState {
     main: Entity,
     join: Entity,
     host: Entity,
+    games: Entity,
 }
 
 #[derive(Debug, Component)]
@@ -30,9 +32,11 @@ pub enum ButtonEvent {
     Solo,
     HostScreen,
     JoinMp,
+    GamesScreen,
     Back,
     IpJoin,
     FriendJoin(u64),
+    SwitchQwak(String),
 }
 
 #[derive(Debug, Component)]
@@ -44,11 +48,8 @@ pub struct FriendButton(u64);
 fn get_mapfiles<P: AsRef<Path>>(dir: P) -> io::Result<Vec<PathBuf>> {
     let mut files = Vec::new();
 
-    let dir = fs::read_dir(dir)?;
-    for f in dir {
-        let f = f?.path();
-
-        if f.is_dir() {
+    for f in vfs::get().read_dir(dir)? {
+        if vfs::get().is_dir(&f) {
             files.append(&mut get_mapfiles(f)?);
         } else {
             files.push(f);
@@ -67,13 +68,25 @@ pub fn buttons(world: &mut World) {
         Option<Res<SteamClient>>,
         Res<MainMenuState>,
         Query<&mut Visibility>,
+        Res<QwakModules>,
+        Res<LaunchArgs>,
+        EventWriter<AppExit>,
     )> = SystemState::new(world);
     // yea this is cursed, but i am lazy, bypassing the borrow checker like a baus
     #[allow(unsafe_code)]
     let world_copy = unsafe { &mut *(world as *mut World) };
 
-    let (query, text_inputs, mut next_net_state, steam_client, state, mut vis) =
-        state.get_mut(world);
+    let (
+        query,
+        text_inputs,
+        mut next_net_state,
+        steam_client,
+        state,
+        mut vis,
+        modules,
+        args,
+        mut exit,
+    ) = state.get_mut(world);
 
     for (interaction, event) in &query {
         if !matches!(interaction, Interaction::Pressed) {
@@ -88,16 +101,25 @@ pub fn buttons(world: &mut World) {
                 *error_continue!(vis.get_mut(state.host)) = Visibility::Visible;
                 *error_continue!(vis.get_mut(state.main)) = Visibility::Hidden;
                 *error_continue!(vis.get_mut(state.join)) = Visibility::Hidden;
+                *error_continue!(vis.get_mut(state.games)) = Visibility::Hidden;
             }
             ButtonEvent::JoinMp => {
                 *error_continue!(vis.get_mut(state.host)) = Visibility::Hidden;
                 *error_continue!(vis.get_mut(state.main)) = Visibility::Hidden;
                 *error_continue!(vis.get_mut(state.join)) = Visibility::Visible;
+                *error_continue!(vis.get_mut(state.games)) = Visibility::Hidden;
+            }
+            ButtonEvent::GamesScreen => {
+                *error_continue!(vis.get_mut(state.host)) = Visibility::Hidden;
+                *error_continue!(vis.get_mut(state.main)) = Visibility::Hidden;
+                *error_continue!(vis.get_mut(state.join)) = Visibility::Hidden;
+                *error_continue!(vis.get_mut(state.games)) = Visibility::Visible;
             }
             ButtonEvent::Back => {
                 *error_continue!(vis.get_mut(state.host)) = Visibility::Hidden;
                 *error_continue!(vis.get_mut(state.main)) = Visibility::Visible;
                 *error_continue!(vis.get_mut(state.join)) = Visibility::Hidden;
+                *error_continue!(vis.get_mut(state.games)) = Visibility::Hidden;
             }
             ButtonEvent::IpJoin => {
                 if steam_client.is_none() {
@@ -113,10 +135,34 @@ pub fn buttons(world: &mut World) {
                     &steam_client,
                 );
             }
+            ButtonEvent::SwitchQwak(name) => {
+                if *name == modules.active.name {
+                    continue;
+                }
+                let args = LaunchArgs {
+                    qwak: Some(name.clone()),
+                    ..args.clone()
+                };
+                error_continue!(modules.relaunch(&args));
+                exit.send(AppExit::Success);
+            }
         }
     }
 }
 
+/// Joins the server given with `--connect`, once.
+pub fn connect_on_launch(world: &mut World) {
+    let Some(address) = world.resource_mut::<LaunchArgs>().connect.take() else {
+        return;
+    };
+    let mut state: SystemState<(ResMut<NextState<NetState>>, Option<Res<SteamClient>>)> =
+        SystemState::new(world);
+    #[allow(unsafe_code)]
+    let world_copy = unsafe { &mut *(world as *mut World) };
+    let (mut next_net_state, steam_client) = state.get_mut(world);
+    net::client::init_client(world_copy, &mut next_net_state, &address, &steam_client);
+}
+
 pub fn clear(query: Query<(Entity, &MainMenuEnt)>, mut commands: Commands) {
     for (ent, _) in &query {
         commands.entity(ent).despawn_recursive();
@@ -180,6 +226,8 @@ pub fn setup(
     mut materials: ResMut<Assets<StandardMaterial>>,
     asset_server: Res<AssetServer>,
     qwaks: Res<Qwaks>,
+    modules: Res<QwakModules>,
+    disconnect_reason: Option<Res<DisconnectReason>>,
 ) {
     commands.insert_resource(AmbientLight {
         brightness: 0.0,
@@ -254,6 +302,7 @@ pub fn setup(
     let mut main = None;
     let mut join = None;
     let mut host = None;
+    let mut games = None;
 
     const FONT_SIZE: Option<f32> = Some(32.0);
     const PADDING: Option<f32> = Some(5.0);
@@ -327,6 +376,13 @@ pub fn setup(
                             BORDER,
                             ButtonEvent::JoinMp,
                         ));
+                        c.spawn(MenuButton::new(
+                            "Games",
+                            FONT_SIZE,
+                            PADDING,
+                            BORDER,
+                            ButtonEvent::GamesScreen,
+                        ));
                     });
                 })
                 .id(),
@@ -341,11 +397,19 @@ pub fn setup(
             })
             .insert((
                 Text::new(format!(
-                    "Running \"{}\" ({})",
-                    qwaks.default.plugin_name().unwrap(),
+                    "Running \"{}\" ({}){}",
+                    qwaks.plugin_name(),
                     {
-                        let version = qwaks.default.plugin_version().unwrap();
+                        let version = qwaks.plugin_version();
                         format!("{}.{}.{}", version[0], version[1], version[2])
+                    },
+                    {
+                        let mutators = qwaks.mutators();
+                        if mutators.is_empty() {
+                            String::new()
+                        } else {
+                            format!(" with {}", mutators.join(", "))
+                        }
                     }
                 )),
                 TextFont {
@@ -354,6 +418,23 @@ pub fn setup(
                 },
             ));
 
+            if let Some(reason) = &disconnect_reason {
+                c.spawn(Node {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(0.0),
+                    left: Val::Px(0.0),
+                    ..default()
+                })
+                .insert((
+                    Text::new(format!("Disconnected: {}", reason.0)),
+                    TextFont {
+                        font_size: 16.0,
+                        ..default()
+                    },
+                    TextColor(Color::srgb(1.0, 0.3, 0.3)),
+                ));
+            }
+
             host = Some(
                 c.spawn(Node {
                     position_type: PositionType::Absolute,
@@ -473,6 +554,53 @@ pub fn setup(
                 })
                 .id(),
             );
+            games = Some(
+                c.spawn(Node {
+                    position_type: PositionType::Absolute,
+                    width: Val::Px(400.0),
+                    border: UiRect::all(Val::Px(2.0)),
+                    left: Val::Px(76.0),
+                    bottom: Val::Px(76.0),
+                    flex_direction: FlexDirection::Column,
+                    ..default()
+                })
+                .insert(Visibility::Hidden)
+                .with_children(|c| {
+                    c.spawn(Node {
+                        padding: UiRect::all(Val::Px(PADDING.unwrap() * 2.0)),
+                        ..default()
+                    })
+                    .with_child((
+                        Text::new("Games:".to_string()),
+                        TextFont {
+                            font_size: FONT_SIZE.unwrap(),
+                            ..default()
+                        },
+                    ));
+                    for module in &modules.installed {
+                        let label = if module.name == modules.active.name {
+                            format!("{} (running)", module.name)
+                        } else {
+                            module.name.clone()
+                        };
+                        c.spawn(MenuButton::new(
+                            label,
+                            Some(16.0),
+                            Some(4.0),
+                            Some(2.0),
+                            ButtonEvent::SwitchQwak(module.name.clone()),
+                        ));
+                    }
+                    c.spawn(MenuButton::new(
+                        "Back",
+                        FONT_SIZE,
+                        PADDING,
+                        BORDER,
+                        ButtonEvent::Back,
+                    ));
+                })
+                .id(),
+            );
         })
         .insert(MainMenuEnt);
 
@@ -480,5 +608,6 @@ pub fn setup(
         main: main.unwrap(),
         join: join.unwrap(),
         host: host.unwrap(),
+        games: games.unwrap(),
     });
 }
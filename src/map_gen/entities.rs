@@ -2,7 +2,10 @@ use crate::{entities::pickup::PickupEntity, map_gen::SCALE_FIX};
 use bevy::{
     asset::{AssetServer, Assets},
     color::Color,
-    ecs::system::{Commands, Res, ResMut},
+    ecs::{
+        entity::Entity,
+        system::{Commands, Res, ResMut},
+    },
     log::error,
     math::{EulerRot, Quat, Vec3},
     pbr::{DirectionalLight, MeshMaterial3d, PointLight, StandardMaterial},
@@ -19,7 +22,7 @@ use qwak_helper_types::PickupData;
 use resources::{PickupMap, PlayerSpawnpoint};
 use std::collections::HashMap;
 
-use super::{GameObject, Interactable};
+use super::{GameObject, Interactable, hot_reload::MapGeometry};
 
 fn parse_vec(str: &str) -> Vec3 {
     let mut splat = str.split_whitespace();
@@ -98,6 +101,7 @@ pub fn spawn_entity(
                 },
                 Transform::from_translation(pos),
                 GameObject,
+                MapGeometry,
                 RenderLayers::from_layers(&[0, 1]),
             ));
         }
@@ -118,6 +122,7 @@ pub fn spawn_entity(
                 },
                 trans,
                 GameObject,
+                MapGeometry,
                 RenderLayers::from_layers(&[0, 1]),
             ));
         }
@@ -154,7 +159,7 @@ pub fn spawn_pickup(
     data: &PickupData,
     commands: &mut Commands,
     materials: &mut ResMut<Assets<StandardMaterial>>,
-) {
+) -> Entity {
     let PickupData {
         pickup_model,
         texture_file,
@@ -192,5 +197,6 @@ pub fn spawn_pickup(
     pickup
         .insert(trans)
         .insert(PickupEntity::new(id, data.clone()))
-        .insert(GameObject);
+        .insert(GameObject)
+        .id()
 }
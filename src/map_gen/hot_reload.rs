@@ -0,0 +1,101 @@
+use crate::net::{ServerChannel, ServerMessage, map_transfer::texture_path};
+use bevy::prelude::*;
+use bevy_renet::renet::RenetServer;
+use faststr::FastStr;
+use macros::error_return;
+use resources::{CurrentMap, TextureMap};
+use std::{collections::HashMap, path::PathBuf, time::SystemTime};
+
+/// Marks the entities which are rebuilt when the map is hot-reloaded.
+/// Players, pickups and projectiles are not part of this.
+#[derive(Debug, Component, Clone, Copy)]
+pub struct MapGeometry;
+
+/// Exists while the map geometry is being rebuilt in place.
+/// Contains the textures which changed on disk.
+#[derive(Debug, Resource, Default)]
+pub struct MapReloading(pub Vec<FastStr>);
+
+/// Developer tool which watches the current map and its textures,
+/// and tells everyone to reload the map when they change.
+#[derive(Debug, Resource)]
+pub struct MapWatcher {
+    timer: Timer,
+    modified: HashMap<PathBuf, SystemTime>,
+}
+impl Default for MapWatcher {
+    fn default() -> Self {
+        Self {
+            timer: Timer::from_seconds(1.0, TimerMode::Repeating),
+            modified: HashMap::new(),
+        }
+    }
+}
+impl MapWatcher {
+    /// Returns true if the file has been modified since it was last checked.
+    /// Files seen for the first time are never counted as modified.
+    fn changed(&mut self, path: PathBuf) -> bool {
+        let Some(path) = vfs::get().disk_path(path) else {
+            return false;
+        };
+        let Ok(modified) = std::fs::metadata(&path).and_then(|m| m.modified()) else {
+            return false;
+        };
+        self.modified
+            .insert(path, modified)
+            .is_some_and(|old| old != modified)
+    }
+
+    pub fn watch(
+        mut watcher: ResMut<MapWatcher>,
+        time: Res<Time>,
+        current_map: Res<CurrentMap>,
+        texture_map: Res<TextureMap>,
+        server: Option<ResMut<RenetServer>>,
+        mut server_events: EventWriter<ServerMessage>,
+    ) {
+        if !watcher.timer.tick(time.delta()).just_finished() {
+            return;
+        }
+
+        let map_changed = watcher.changed(current_map.0.clone());
+        let textures = texture_map
+            .0
+            .keys()
+            .filter(|t| watcher.changed(texture_path(&current_map.0, t).into()))
+            .cloned()
+            .collect::<Vec<_>>();
+        if !map_changed && textures.is_empty() {
+            return;
+        }
+
+        info!("map changed on disk, reloading...");
+        let msg = ServerMessage::ReloadMap { textures };
+        if let Some(mut server) = server {
+            server.broadcast_message(
+                ServerChannel::ServerMessages as u8,
+                error_return!(msg.bytes()),
+            );
+        }
+        server_events.send(msg);
+    }
+}
+
+/// Despawns the map geometry and resets the loading state, which makes
+/// [load_map](super::load_map) build the map again.
+pub fn reload_map(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    reloading: Res<MapReloading>,
+    current_map: Res<CurrentMap>,
+    query: Query<Entity, With<MapGeometry>>,
+) {
+    info!("Reloading map...");
+    for texture in &reloading.0 {
+        asset_server.reload(texture_path(&current_map.0, texture));
+    }
+    for ent in &query {
+        commands.entity(ent).despawn_recursive();
+    }
+    super::reset_map_loading(&mut commands);
+}
@@ -0,0 +1,83 @@
+use super::GameObject;
+use bevy::{
+    audio::{AudioPlayer, PlaybackSettings},
+    pbr::{DistanceFog, FogFalloff},
+    prelude::*,
+};
+use bevy_atmosphere::plugin::AtmosphereCamera;
+use faststr::FastStr;
+use map_parser::parser::Entity as MapEntity;
+use resources::map_settings::MapSettings;
+
+/// The looping music of the current map.
+#[derive(Debug, Component)]
+pub struct MapMusic;
+
+/// Reads the [MapSettings] from the worldspawn entity of a parsed map.
+pub fn read_map_settings(map: &[MapEntity]) -> MapSettings {
+    map.iter()
+        .find(|e| {
+            e.attributes
+                .get(&FastStr::from("classname"))
+                .is_some_and(|c| &c[..] == "worldspawn")
+        })
+        .map(|e| MapSettings::from_attributes(&e.attributes))
+        .unwrap_or_default()
+}
+
+/// Applies the [MapSettings] to the world.
+/// Runs again when the settings change, or when a new camera is spawned.
+pub fn apply_map_settings(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    settings: Res<MapSettings>,
+    mut ambient: ResMut<AmbientLight>,
+    mut cameras: Query<(Entity, &mut Camera, Ref<Camera3d>)>,
+    music: Query<Entity, With<MapMusic>>,
+) {
+    let changed = settings.is_changed();
+    if changed {
+        info!(
+            "Playing \"{}\" by \"{}\"",
+            settings.message, settings.author
+        );
+        ambient.brightness = settings.ambient;
+
+        for ent in &music {
+            commands.entity(ent).despawn_recursive();
+        }
+        if let Some(music) = &settings.music {
+            commands.spawn((
+                AudioPlayer::<AudioSource>(asset_server.load(music.to_string())),
+                PlaybackSettings::LOOP,
+                MapMusic,
+                GameObject,
+            ));
+        }
+    }
+
+    for (ent, mut camera, cam3d) in &mut cameras {
+        if !changed && !cam3d.is_added() {
+            continue;
+        }
+        let mut cc = commands.entity(ent);
+
+        if settings.fog_density > 0.0 {
+            let Vec3 { x, y, z } = settings.fog_color;
+            cc.insert(DistanceFog {
+                color: Color::srgb(x, y, z),
+                falloff: FogFalloff::Exponential {
+                    density: settings.fog_density,
+                },
+                ..default()
+            });
+        } else {
+            cc.remove::<DistanceFog>();
+        }
+
+        if let Some(Vec3 { x, y, z }) = settings.sky {
+            cc.remove::<AtmosphereCamera>();
+            camera.clear_color = ClearColorConfig::Custom(Color::srgb(x, y, z));
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc, time::Instant};
 
 use self::{
     plane::{InPlane, Plane},
@@ -13,20 +13,24 @@ use bevy::{
         render_resource::{PrimitiveTopology, encase::rts_array::Length},
         view::RenderLayers,
     },
+    tasks::{AsyncComputeTaskPool, Task, block_on, futures_lite::future},
 };
 use bevy_rapier3d::{geometry::Collider, prelude::RigidBody};
 use bevy_renet::renet::RenetClient;
 use entities::spawn_entity;
 use faststr::FastStr;
+use hot_reload::{MapGeometry, MapReloading};
 use macros::error_return;
 use map_parser::parser::Brush;
 use resources::{
-    CurrentMap, MapDoneLoading, MapFirstRun, Paused, PickupMap, PlayerSpawned, PlayerSpawnpoint,
-    TargetMap, TextureLoadingState, TextureMap, TexturesLoading,
+    ChangingMap, CurrentMap, MapFirstRun, MapLoadingProgress, MapLoadingState, Paused, PickupMap,
+    PlayerSpawned, PlayerSpawnpoint, TargetMap, TextureMap, TexturesLoading,
 };
 
 pub mod entities;
+pub mod hot_reload;
 mod interactable;
+pub mod map_settings;
 mod plane;
 mod poly;
 pub mod texture_systems;
@@ -50,19 +54,24 @@ pub struct BrushEntity;
 #[derive(Debug, Component, Clone, Copy)]
 pub struct GameObject;
 
-pub fn clean_up_map(
-    query: Query<(Entity, Option<&Name>), With<GameObject>>,
-    mut commands: Commands,
-) {
-    commands.insert_resource(TextureLoadingState::NotLoaded);
-    commands.insert_resource(PlayerSpawned(false));
+/// Resets the resources needed for [load_map] to run again.
+fn reset_map_loading(commands: &mut Commands) {
+    commands.insert_resource(MapLoadingState::NotLoaded);
+    commands.insert_resource(MapLoadingProgress::default());
     commands.insert_resource(TexturesLoading::default());
     commands.insert_resource(TextureMap::default());
+    commands.remove_resource::<MapParseTask>();
+    commands.remove_resource::<MapBuildTasks>();
+}
+
+/// Despawns every [GameObject] and resets the map resources.
+fn despawn_map(commands: &mut Commands, entities: impl Iterator<Item = Entity>) {
+    reset_map_loading(commands);
+    commands.insert_resource(PlayerSpawned(false));
     commands.insert_resource(PlayerSpawnpoint(Vec3::ZERO));
-    commands.insert_resource(MapDoneLoading(false));
     commands.insert_resource(Paused(false));
     commands.insert_resource(MapFirstRun(true));
-    for (ent, _name) in &query {
+    for ent in entities {
         let Some(mut cc) = commands.get_entity(ent) else {
             continue;
         };
@@ -72,25 +81,121 @@ pub fn clean_up_map(
     }
 }
 
+pub fn clean_up_map(
+    query: Query<(Entity, Option<&Name>), With<GameObject>>,
+    mut commands: Commands,
+) {
+    despawn_map(&mut commands, query.iter().map(|(ent, _)| ent));
+}
+
+/// Tears down the current map so [CurrentMap] is loaded instead, without leaving the game.
+pub fn change_map(query: Query<Entity, With<GameObject>>, mut commands: Commands) {
+    info!("Changing map...");
+    despawn_map(&mut commands, query.iter());
+    commands.remove_resource::<ChangingMap>();
+}
+
+/// Size and visibility of a texture, gathered before the map geometry
+/// is built off the main thread.
+#[derive(Debug, Clone, Copy)]
+struct TextureInfo {
+    size: Vec2,
+    invisible: bool,
+}
+
+#[derive(Debug)]
+enum FaceMaterial {
+    Textured(FastStr),
+    Invisible,
+    Missing,
+}
+
+struct FaceGeometry {
+    mesh: Mesh,
+    material: FaceMaterial,
+}
+
+/// The finished geometry of a single brush.
+struct BrushGeometry {
+    center: Vec3,
+    faces: Vec<FaceGeometry>,
+    collider: Option<Collider>,
+}
+
+struct PendingBrush {
+    entity: Option<Entity>,
+    interactable: Option<Interactable>,
+    task: Option<Task<BrushGeometry>>,
+}
+
+/// The map file which is being read and parsed, started by [read_map].
+#[derive(Resource)]
+pub struct MapParseTask {
+    task: Task<std::io::Result<Vec<map_parser::Entity>>>,
+    started: Instant,
+}
+
+/// The brushes which are currently being built by [load_map].
+#[derive(Resource)]
+pub struct MapBuildTasks {
+    pending: Vec<PendingBrush>,
+    started: Instant,
+}
+
+/// The max amount of finished brushes added to the world each frame.
+const BRUSHES_PER_FRAME: usize = 64;
+
+/// Reads and parses [CurrentMap] off the main thread, for [load_map] to build.
+pub fn read_map(mut commands: Commands, current_map: Res<CurrentMap>) {
+    info!("Loading map...");
+    let path = current_map.0.clone();
+    let task = AsyncComputeTaskPool::get()
+        .spawn(async move { map_parser::parse(&vfs::read_to_string(&path)?) });
+    commands.insert_resource(MapParseTask {
+        task,
+        started: Instant::now(),
+    });
+}
+
 #[allow(clippy::too_many_arguments)]
 pub fn load_map(
     client: Option<Res<RenetClient>>,
     asset_server: Res<AssetServer>,
     mut commands: Commands,
     images: Res<Assets<Image>>,
-    current_map: Res<CurrentMap>,
-    mut meshes: ResMut<Assets<Mesh>>,
+    mut parse: ResMut<MapParseTask>,
     mut materials: ResMut<Assets<StandardMaterial>>,
     pickup_map: Res<PickupMap>,
     texture_map: Res<TextureMap>,
-    mut done_loading: ResMut<MapDoneLoading>,
+    mut progress: ResMut<MapLoadingProgress>,
     mut player_spawn: ResMut<PlayerSpawnpoint>,
+    reloading: Option<Res<MapReloading>>,
 ) {
-    let map = error_return!(std::fs::read_to_string(&current_map.0));
-    let map = error_return!(map_parser::parse(&map));
+    let Some(map) = block_on(future::poll_once(&mut parse.task)) else {
+        return;
+    };
+    let started = parse.started;
+    commands.remove_resource::<MapParseTask>();
+    let map = error_return!(map);
+
+    let textures = Arc::new(
+        texture_map
+            .0
+            .iter()
+            .map(|(name, handle)| {
+                let size = images
+                    .get(handle)
+                    .map(|i| i.size().as_vec2())
+                    .unwrap_or(Vec2::ONE);
+                // very hacky :)
+                let invisible = handle
+                    .path()
+                    .is_some_and(|p| format!("{p}").ends_with("Invisible.png"));
+                (name.clone(), TextureInfo { size, invisible })
+            })
+            .collect::<HashMap<_, _>>(),
+    );
 
-    let t = std::time::Instant::now();
-    info!("Loading map...");
     let mut targets = HashMap::new();
     let mut target_index: HashMap<FastStr, usize> = HashMap::new();
     let tn = FastStr::from("targetname");
@@ -104,6 +209,7 @@ pub fn load_map(
                         Transform::default(),
                         RigidBody::KinematicPositionBased,
                         GameObject,
+                        MapGeometry,
                         InheritedVisibility::VISIBLE,
                     ))
                     .id(),
@@ -111,6 +217,8 @@ pub fn load_map(
         }
     }
 
+    let pool = AsyncComputeTaskPool::get();
+    let mut pending = Vec::new();
     for (id, entity) in map.into_iter().enumerate() {
         let predefined = entity
             .attributes
@@ -121,9 +229,10 @@ pub fn load_map(
                 *entry += 1;
                 targets.get(&en).map(|v| v[e])
             });
+        // Pickups are owned by the server and survive a reload
         let interactable = spawn_entity(
             id as u64,
-            client.is_some(),
+            client.is_some() || reloading.is_some(),
             &asset_server,
             entity.attributes,
             &mut commands,
@@ -133,121 +242,188 @@ pub fn load_map(
         );
 
         for brush in entity.brushes {
-            // Calculate the verticies for the mesh
-            let polys = sort_verticies_cw(get_polys_brush(brush));
+            let textures = textures.clone();
+            pending.push(PendingBrush {
+                entity: predefined,
+                interactable: interactable.clone(),
+                task: Some(pool.spawn(async move { build_brush(brush, &textures) })),
+            });
+        }
+    }
+
+    *progress = MapLoadingProgress {
+        done: 0,
+        total: pending.len(),
+    };
+    commands.insert_resource(TargetMap(targets));
+    commands.insert_resource(MapBuildTasks { pending, started });
+}
 
-            let mut spawner = match predefined {
-                Some(ent) => commands.get_entity(ent).unwrap(),
-                None => commands.spawn((BrushEntity, InheritedVisibility::VISIBLE)),
+/// Adds the brushes built by [load_map] to the world as they finish.
+pub fn poll_map_build(
+    mut commands: Commands,
+    mut tasks: ResMut<MapBuildTasks>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    texture_map: Res<TextureMap>,
+    mut progress: ResMut<MapLoadingProgress>,
+    mut loading_state: ResMut<MapLoadingState>,
+) {
+    let mut added = 0;
+    for pending in &mut tasks.pending {
+        if added >= BRUSHES_PER_FRAME {
+            break;
+        }
+        let Some(task) = &mut pending.task else {
+            continue;
+        };
+        let Some(brush) = block_on(future::poll_once(task)) else {
+            continue;
+        };
+        pending.task = None;
+        added += 1;
+
+        let mut spawner = match pending.entity {
+            Some(ent) => commands.entity(ent),
+            None => commands.spawn((BrushEntity, InheritedVisibility::VISIBLE)),
+        };
+        spawner.insert((
+            GameObject,
+            MapGeometry,
+            Transform::from_translation(brush.center),
+        ));
+        for face in brush.faces {
+            let mat = match face.material {
+                FaceMaterial::Textured(text) => StandardMaterial {
+                    base_color: Color::srgb(1.0, 1.0, 1.0),
+                    base_color_texture: texture_map.0.get(&text).cloned(),
+                    unlit: false,
+                    perceptual_roughness: 1.0,
+                    reflectance: 0.0,
+                    ..default()
+                },
+                FaceMaterial::Invisible => StandardMaterial {
+                    base_color: Color::srgba(0.0, 0.0, 0.0, 0.0),
+                    alpha_mode: AlphaMode::Blend,
+                    ..default()
+                },
+                FaceMaterial::Missing => StandardMaterial {
+                    base_color: Color::srgb(0.0, 1.0, 0.0),
+                    alpha_mode: AlphaMode::Blend,
+                    unlit: true,
+                    ..default()
+                },
             };
-            spawner.insert(GameObject);
-            let mut brush_poly = Vec::new();
-            let mut model_center = Vec3::ZERO;
-            for poly in &polys {
-                let mut plane_center = Vec3::ZERO;
-                for vert in &poly.verts {
-                    plane_center += vert.p;
-                }
-                plane_center /= poly.verts.len() as f32;
-                model_center += plane_center;
+            spawner.with_children(|f| {
+                f.spawn((
+                    Mesh3d(meshes.add(face.mesh)),
+                    MeshMaterial3d(materials.add(mat)),
+                    Transform::default(),
+                    RenderLayers::from_layers(&[0]),
+                ));
+            });
+        }
+        if let Some(col) = brush.collider {
+            spawner.insert(col);
+            if let Some(interactable) = &pending.interactable {
+                spawner.insert(interactable.clone());
             }
-            model_center /= polys.len() as f32;
-            spawner.insert(Transform::from_translation(model_center));
+        }
+    }
+    tasks.pending.retain(|p| p.task.is_some());
+    progress.done = progress.total - tasks.pending.len();
 
-            for mut poly in polys {
-                let mut plane_center = Vec3::ZERO;
-                for vert in &poly.verts {
-                    plane_center += vert.p;
-                }
-                plane_center /= poly.verts.len() as f32;
-
-                let indices = poly.calculate_indices();
-                let verts = poly
-                    .verts
-                    .iter()
-                    .map(|p| p.p - model_center)
-                    .collect::<Vec<_>>();
-                brush_poly.append(&mut verts.clone());
-                let mut new_mesh = Mesh::new(
-                    PrimitiveTopology::TriangleList,
-                    RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
-                )
-                .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, verts)
-                .with_inserted_indices(Indices::U32(indices));
-
-                let mat = if let Some(text) = poly.texture.clone() {
-                    let uv = poly.calculate_textcoords(&images, &texture_map);
+    if tasks.pending.is_empty() {
+        info!(
+            "Done loading map, took {}s",
+            tasks.started.elapsed().as_secs_f32()
+        );
+        commands.remove_resource::<MapBuildTasks>();
+        commands.remove_resource::<MapReloading>();
+        *loading_state = MapLoadingState::Done;
+    }
+}
+
+/// Calculates the meshes and collider of a brush.
+/// Runs on the [AsyncComputeTaskPool], so it may not touch the world.
+fn build_brush(brush: Brush, textures: &HashMap<FastStr, TextureInfo>) -> BrushGeometry {
+    // Calculate the verticies for the mesh
+    let polys = sort_verticies_cw(get_polys_brush(brush));
+
+    let mut brush_poly = Vec::new();
+    let mut model_center = Vec3::ZERO;
+    for poly in &polys {
+        let mut plane_center = Vec3::ZERO;
+        for vert in &poly.verts {
+            plane_center += vert.p;
+        }
+        plane_center /= poly.verts.len() as f32;
+        model_center += plane_center;
+    }
+    model_center /= polys.len() as f32;
+
+    let mut faces = Vec::new();
+    for mut poly in polys {
+        let indices = poly.calculate_indices();
+        let verts = poly
+            .verts
+            .iter()
+            .map(|p| p.p - model_center)
+            .collect::<Vec<_>>();
+        brush_poly.append(&mut verts.clone());
+        let mut mesh = Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+        )
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, verts)
+        .with_inserted_indices(Indices::U32(indices));
+
+        let material = match poly.texture.clone() {
+            Some(text) => match textures.get(&text) {
+                Some(info) if info.invisible => FaceMaterial::Invisible,
+                Some(info) => {
+                    let uv = poly.calculate_textcoords(info.size);
                     let tangent = poly.calculate_tangent();
-                    let texture_handle = texture_map
-                        .0
-                        .get(&text)
-                        .unwrap_or_else(|| panic!("missing texture: {text:?}"));
-                    let path = texture_handle.path().unwrap();
-                    // very hacky :)
-                    if !format!("{path}").ends_with("Invisible.png") {
-                        new_mesh = new_mesh
-                            .with_inserted_attribute(
-                                Mesh::ATTRIBUTE_UV_0,
-                                VertexAttributeValues::Float32x2(uv),
-                            )
-                            .with_inserted_attribute(
-                                Mesh::ATTRIBUTE_TANGENT,
-                                VertexAttributeValues::Float32x4(tangent),
-                            );
-                        StandardMaterial {
-                            base_color: Color::srgb(1.0, 1.0, 1.0),
-                            base_color_texture: Some(texture_handle.clone()),
-                            unlit: false,
-                            perceptual_roughness: 1.0,
-                            reflectance: 0.0,
-                            ..default()
-                        }
-                    } else {
-                        StandardMaterial {
-                            base_color: Color::srgba(0.0, 0.0, 0.0, 0.0),
-                            alpha_mode: AlphaMode::Blend,
-                            ..default()
-                        }
-                    }
-                } else {
-                    StandardMaterial {
-                        base_color: Color::srgb(0.0, 1.0, 0.0),
-                        alpha_mode: AlphaMode::Blend,
-                        unlit: true,
-                        ..default()
-                    }
-                };
-                if new_mesh.count_vertices() != 0 {
-                    new_mesh.duplicate_vertices();
-                    new_mesh.compute_flat_normals();
-
-                    spawner.with_children(|f| {
-                        f.spawn((
-                            Mesh3d(meshes.add(new_mesh)),
-                            MeshMaterial3d(materials.add(mat)),
-                            Transform::default(),
-                            RenderLayers::from_layers(&[0]),
-                        ));
-                    });
+                    mesh = mesh
+                        .with_inserted_attribute(
+                            Mesh::ATTRIBUTE_UV_0,
+                            VertexAttributeValues::Float32x2(uv),
+                        )
+                        .with_inserted_attribute(
+                            Mesh::ATTRIBUTE_TANGENT,
+                            VertexAttributeValues::Float32x4(tangent),
+                        );
+                    FaceMaterial::Textured(text)
                 }
-            }
-
-            if !brush_poly.is_empty() {
-                if let Some(col) = Collider::convex_hull(&brush_poly) {
-                    spawner.insert(col);
-                    if let Some(interactable) = &interactable {
-                        spawner.insert((*interactable).clone());
-                    }
-                } else {
-                    error!("failed to create collider!!");
+                None => {
+                    error!("missing texture: {text:?}");
+                    FaceMaterial::Missing
                 }
-            }
+            },
+            None => FaceMaterial::Missing,
+        };
+        if mesh.count_vertices() != 0 {
+            mesh.duplicate_vertices();
+            mesh.compute_flat_normals();
+            faces.push(FaceGeometry { mesh, material });
         }
     }
 
-    info!("Done loading map, took {}s", t.elapsed().as_secs_f32());
-    commands.insert_resource(TargetMap(targets));
-    done_loading.0 = true;
+    let collider = if brush_poly.is_empty() {
+        None
+    } else {
+        let col = Collider::convex_hull(&brush_poly);
+        if col.is_none() {
+            error!("failed to create collider!!");
+        }
+        col
+    };
+
+    BrushGeometry {
+        center: model_center,
+        faces,
+        collider,
+    }
 }
 
 fn sort_verticies_cw(polys: Vec<Poly>) -> Vec<Poly> {
@@ -1,14 +1,7 @@
 use super::{SCALE_FIX, plane::Plane, vertex::Vertex};
-use bevy::{
-    asset::Assets,
-    ecs::system::Res,
-    image::Image,
-    math::{Quat, Vec2, Vec3},
-};
+use bevy::math::{Quat, Vec2, Vec3};
 use faststr::FastStr;
-use macros::error_return;
 use map_parser::parser::TextureOffset;
-use resources::TextureMap;
 use std::ops::Div;
 
 const UP_VECTOR: Vec3 = Vec3::Z;
@@ -90,17 +83,11 @@ impl Poly {
         vec![[u_axis.x, u_axis.y, u_axis.z, v_sign]; self.verts.len()]
     }
 
-    pub fn calculate_textcoords(
-        &mut self,
-        images: &Res<Assets<Image>>,
-        texture_map: &TextureMap,
-    ) -> Vec<[f32; 2]> {
-        let tex_ref = error_return!(self.texture.as_ref().ok_or("missing texture for object"));
-        let tex = error_return!(texture_map.0.get(tex_ref).ok_or("missing texture in map"));
-        let tex = error_return!(images.get(tex).ok_or("missing texture"));
-
-        let tex_width = tex.texture_descriptor.size.width as f32;
-        let tex_height = tex.texture_descriptor.size.height as f32;
+    pub fn calculate_textcoords(&mut self, texture_size: Vec2) -> Vec<[f32; 2]> {
+        let Vec2 {
+            x: tex_width,
+            y: tex_height,
+        } = texture_size;
 
         if let TextureOffset::V220(..) = self.x_offset {
             self.pipe_textcoords(tex_width, tex_height)
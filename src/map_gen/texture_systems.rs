@@ -1,20 +1,32 @@
+use super::map_settings::read_map_settings;
+use crate::net::map_transfer::texture_path;
 use bevy::{asset::LoadState, prelude::*};
+use bevy_renet::renet::RenetClient;
 use macros::error_return;
-use resources::{CurrentMap, TextureLoadingState, TextureMap, TexturesLoading};
+use resources::{CurrentMap, MapLoadingProgress, MapLoadingState, TextureMap, TexturesLoading};
 use std::collections::HashMap;
 
+#[allow(clippy::too_many_arguments)]
 pub fn register_textures(
+    mut commands: Commands,
+    client: Option<Res<RenetClient>>,
     asset_server: Res<AssetServer>,
     current_map: Res<CurrentMap>,
     mut textures_loading: ResMut<TexturesLoading>,
-    mut loading_state: ResMut<TextureLoadingState>,
+    mut loading_state: ResMut<MapLoadingState>,
+    mut progress: ResMut<MapLoadingProgress>,
     mut texture_map: ResMut<TextureMap>,
 ) {
     info!("Registering textures...");
     let time = std::time::Instant::now();
-    let map = error_return!(std::fs::read_to_string(&current_map.0));
+    let map = error_return!(vfs::read_to_string(&current_map.0));
     let map = error_return!(map_parser::parse(&map));
 
+    // Clients get the settings from the server
+    if client.is_none() {
+        commands.insert_resource(read_map_settings(&map));
+    }
+
     let mut textures = map
         .into_iter()
         .flat_map(|e| e.brushes)
@@ -31,7 +43,7 @@ pub fn register_textures(
         //    &format!("textures/{texture}.png"),
         //    |s| {s.sampler.},
         //);
-        let handle = asset_server.load::<Image>(&format!("textures/{texture}.png"));
+        let handle = asset_server.load::<Image>(texture_path(&current_map.0, &texture));
         textures_loading.0.push(handle.clone().untyped());
         map.insert(texture, handle);
     }
@@ -40,13 +52,18 @@ pub fn register_textures(
         "Done registering textures, took {}s",
         time.elapsed().as_secs_f32()
     );
-    *loading_state = TextureLoadingState::Loading;
+    *progress = MapLoadingProgress {
+        done: 0,
+        total: textures_loading.0.len(),
+    };
+    *loading_state = MapLoadingState::LoadingTextures;
 }
 
 pub fn texture_waiter(
     mut textures_loading: ResMut<TexturesLoading>,
     asset_server: Res<AssetServer>,
-    mut loading_state: ResMut<TextureLoadingState>,
+    mut loading_state: ResMut<MapLoadingState>,
+    mut progress: ResMut<MapLoadingProgress>,
 ) {
     let mut to_remove = Vec::new();
     for (i, tex) in textures_loading.0.iter().enumerate() {
@@ -63,9 +80,10 @@ pub fn texture_waiter(
     for (offset, i) in to_remove.into_iter().enumerate() {
         textures_loading.0.remove(i - offset);
     }
+    progress.done = progress.total - textures_loading.0.len();
 
     if textures_loading.0.is_empty() {
         info!("Texture loading done...");
-        *loading_state = TextureLoadingState::Done;
+        *loading_state = MapLoadingState::BuildingGeometry;
     }
 }
@@ -1,11 +1,9 @@
-use crate::{net::ServerMessage, plugins::Qwaks, queries::NetWorld, set_nw};
+use crate::{queries::NetWorld, qwaks::Qwaks};
 
 use super::BrushEntity;
-use crate::net::server::NW_PTR;
 use bevy::{ecs::schedule::SystemConfigs, math::Vec3, prelude::*, time::Time};
 use bevy_rapier3d::prelude::RigidBody;
 use bevy_renet::renet::RenetServer;
-use macros::error_continue;
 use qwak_helper_types::MapInteraction;
 
 #[derive(Debug, Component, Clone)]
@@ -24,7 +22,6 @@ impl Timer {
     pub fn update(
         mut nw: NetWorld,
         server: Option<Res<RenetServer>>,
-        events: EventWriter<ServerMessage>,
         qwaks: Res<Qwaks>,
         time: Res<Time>,
         mut query: Query<(Entity, &mut Timer)>,
@@ -32,10 +29,9 @@ impl Timer {
         for (ent, mut timer) in &mut query {
             timer.current_time -= time.delta_secs();
             if timer.current_time <= 0.0 {
-                if let Some(server) = &server {
+                if server.is_some() {
                     nw.commands.entity(ent).despawn();
-                    set_nw!(&nw, server, &events);
-                    error_continue!(qwaks.default.map_interact(timer.map_interact.clone()));
+                    qwaks.map_interact(timer.map_interact.clone());
                 }
             }
         }
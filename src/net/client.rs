@@ -1,30 +1,41 @@
 use super::{
-    CurrentClientId, IsSteam, NetState, PROTOCOL_ID, ServerChannel, ServerMessage, SteamClient,
+    AssetManifest, ClientChannel, ClientMessage, ConnectInfo, CurrentClientId, DisconnectReason,
+    IsSteam, NetState, PROTOCOL_ID, ServerAddress, ServerChannel, ServerMessage, SteamClient,
     connection_config, update_world,
 };
 use crate::{
-    entities::{hitscan_hit_gfx, pickup::PickupEntity},
+    entities::{PluginEntity, ProjectileEntity, PropEntity, hitscan_hit_gfx, pickup::PickupEntity},
     map_gen::{
         self,
+        hot_reload::MapReloading,
         world_entites::{RotateBrush, Timer, TranslateBrush},
     },
-    net::{Lobby, PlayerInfo},
+    net::{
+        Lobby, PlayerInfo,
+        map_transfer::{self, MapDownload},
+    },
     player::Player,
     queries::NetWorld,
+    qwak_modules::{LaunchArgs, QwakModules},
+    ui::{
+        hud::{self, HudElement},
+        plugin_menu::{self, PluginMenu},
+    },
 };
 use bevy::{
+    app::AppExit,
     audio::{AudioPlayer, PlaybackSettings, Volume},
     ecs::{
         entity::Entity,
         event::EventReader,
         schedule::{IntoSystemConfigs, SystemConfigs, common_conditions::resource_exists},
-        system::{Query, Res, ResMut},
+        system::{Local, Query, Res, ResMut},
         world::World,
     },
     hierarchy::DespawnRecursiveExt,
     log::{error, info},
     math::{EulerRot, Vec3},
-    prelude::{Commands, EventWriter, NextState, Visibility, With},
+    prelude::{Commands, EventWriter, NextState, State, Visibility, With},
     window::{CursorGrabMode, PrimaryWindow, Window},
 };
 use bevy_renet::{
@@ -34,7 +45,7 @@ use bevy_renet::{
 };
 use macros::{error_continue, error_return, option_continue, option_return};
 use renet_steam::SteamClientTransport;
-use resources::{CurrentMap, CurrentStage};
+use resources::{ChangingMap, CurrentMap, CurrentStage};
 use std::{net::UdpSocket, time::SystemTime};
 use steamworks::SteamId;
 
@@ -49,10 +60,37 @@ pub fn get_events(mut client: ResMut<RenetClient>, mut server_events: EventWrite
     }
 }
 
+/// Sends our [ConnectInfo] once connected, as Steam has no netcode user data.
+pub fn send_handshake(
+    mut client: ResMut<RenetClient>,
+    info: Res<ConnectInfo>,
+    mut sent: Local<bool>,
+) {
+    if !client.is_connected() {
+        *sent = false;
+        return;
+    }
+    if *sent {
+        return;
+    }
+    *sent = true;
+    client.send_message(
+        ClientChannel::Command as u8,
+        error_return!(ClientMessage::Handshake(info.clone()).bytes()),
+    );
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn handle_messages(
     pickups: Query<(Entity, &PickupEntity)>,
+    plugin_entities: Query<(Entity, &PluginEntity)>,
+    hud_elements: Query<(Entity, &HudElement)>,
+    menus: Query<(Entity, &PluginMenu)>,
     mut current_stage: ResMut<CurrentMap>,
     mut state: ResMut<NextState<CurrentStage>>,
+    mut net_state: ResMut<NextState<NetState>>,
+    asset_manifest: Res<AssetManifest>,
+    stage: Res<State<CurrentStage>>,
     mut nw: NetWorld,
     mut server_events: EventReader<ServerMessage>,
     mut visiblities: Query<&mut Visibility>,
@@ -64,6 +102,7 @@ pub fn handle_messages(
                 for (_, mut player, _) in &mut nw.players {
                     if player.id == id {
                         player.dead = true;
+                        player.powerups.clear();
                         if nw.current_id.0 == id {
                             *error_continue!(
                                 visiblities.get_mut(option_continue!(player.children.death_splash))
@@ -84,10 +123,61 @@ pub fn handle_messages(
                     PlaybackSettings::DESPAWN.with_volume(Volume::new(volume)),
                 ));
             }
-            ServerMessage::SetMap(map) => {
+            ServerMessage::Disconnect {
+                reason,
+                manifest,
+                module,
+                mutators,
+            } => {
+                error!("disconnected by the server: {reason}");
+                let reason = match manifest.map(|m| asset_manifest.0.diff(&m)) {
+                    Some(diff) if !diff.is_empty() => {
+                        error!("assets differing from the server:\n{diff}");
+                        let count = diff.missing.len() + diff.extra.len() + diff.changed.len();
+                        format!("{reason} ({count} files differ, see the log)")
+                    }
+                    _ => reason,
+                };
+                if let Some(module) = module {
+                    nw.commands
+                        .queue(move |world: &mut World| rejoin_with(world, module, mutators));
+                }
+                nw.commands.insert_resource(DisconnectReason(reason));
+                net_state.set(NetState::Offline);
+                state.set(CurrentStage::MainMenu);
+            }
+            ServerMessage::SetMap {
+                map,
+                hash,
+                settings,
+            } => {
                 info!("setting map to: {map:?}");
-                current_stage.0 = map;
-                state.set(CurrentStage::InGame);
+                match map_transfer::find_map(&map, &hash) {
+                    Some(local) => current_stage.0 = local,
+                    None => {
+                        let Some(download) = MapDownload::new(map.clone(), hash) else {
+                            error!("the server sent an invalid map hash");
+                            nw.commands.insert_resource(DisconnectReason(
+                                "the server sent an invalid map".to_string(),
+                            ));
+                            net_state.set(NetState::Offline);
+                            state.set(CurrentStage::MainMenu);
+                            continue;
+                        };
+                        current_stage.0 = map;
+                        nw.commands.insert_resource(download);
+                    }
+                }
+                nw.commands.insert_resource(settings);
+                // Leaving the game would disconnect us, so the map is swapped in place
+                if *stage.get() == CurrentStage::InGame {
+                    nw.commands.insert_resource(ChangingMap);
+                } else {
+                    state.set(CurrentStage::InGame);
+                }
+            }
+            ServerMessage::ReloadMap { textures } => {
+                nw.commands.insert_resource(MapReloading(textures));
             }
             ServerMessage::SpawnPlayer {
                 id,
@@ -96,8 +186,13 @@ pub fn handle_messages(
                 name,
             } => {
                 if id != nw.current_id.0 {
-                    let entity = Player::spawn(&mut nw, false, translation, id, weapons, None);
-                    nw.lobby.insert(id, PlayerInfo::new(entity, name));
+                    let entity =
+                        Player::spawn(&mut nw, false, translation, id, weapons, None, false);
+                    // Players already in the lobby keep their stats across map changes
+                    nw.lobby
+                        .entry(id)
+                        .and_modify(|info| info.entity = entity)
+                        .or_insert_with(|| PlayerInfo::new(entity, name));
                 }
             }
             ServerMessage::DespawnPlayer { id } => {
@@ -127,6 +222,65 @@ pub fn handle_messages(
                     &mut nw.materials,
                 );
             }
+            ServerMessage::SpawnProjectile {
+                id,
+                translation,
+                dir,
+                projectile,
+            } => {
+                let proj = option_continue!(nw.projectile_map.0.get(&projectile));
+                let entity = ProjectileEntity::spawn(
+                    &mut nw.commands,
+                    &nw.asset_server,
+                    &mut nw.materials,
+                    proj,
+                    translation,
+                    dir,
+                );
+                nw.commands.entity(entity).insert(PluginEntity(id));
+            }
+            ServerMessage::SpawnProp {
+                id,
+                translation,
+                scale,
+                model,
+            } => {
+                let entity = PropEntity::spawn(
+                    &mut nw.commands,
+                    &nw.asset_server,
+                    model,
+                    translation,
+                    scale,
+                );
+                nw.commands.entity(entity).insert(PluginEntity(id));
+            }
+            ServerMessage::DespawnEntity { id } => {
+                for (ent, entity) in &plugin_entities {
+                    if entity.0 == id {
+                        nw.commands.entity(ent).despawn_recursive();
+                    }
+                }
+                for (ent, pickup) in &pickups {
+                    if pickup.id == id {
+                        nw.commands.entity(ent).despawn_recursive();
+                    }
+                }
+            }
+            ServerMessage::SetHud {
+                name,
+                widget,
+                global,
+            } => hud::set(
+                &mut nw.commands,
+                &nw.asset_server,
+                &hud_elements,
+                name,
+                widget,
+                global,
+            ),
+            ServerMessage::OpenMenu { name, menu } => {
+                plugin_menu::set(&mut nw.commands, &menus, name, menu)
+            }
             ServerMessage::Message { text } => {
                 let player = option_continue!(nw.lobby.get(&nw.current_id.0)).entity;
                 let (_, player, _) = error_continue!(nw.players.get(player));
@@ -212,7 +366,7 @@ pub fn handle_messages(
             ServerMessage::Hit { amount } => {
                 let player = option_continue!(nw.lobby.get(&nw.current_id.0)).entity;
                 let (_, mut player, _) = error_continue!(nw.players.get_mut(player));
-                player.health -= amount;
+                player.take_damage(amount);
                 nw.commands.spawn((
                     AudioPlayer::new(nw.asset_server.load("sounds/BulletHit.ogg")),
                     PlaybackSettings::DESPAWN.with_volume(Volume::new(0.5)),
@@ -268,6 +422,24 @@ pub fn handle_messages(
     }
 }
 
+/// Restarts the game running the server's qwak module and mutators and joins it again,
+/// if the module is installed. The other launch arguments are kept.
+fn rejoin_with(world: &mut World, module: String, mutators: Vec<String>) {
+    let modules = world.resource::<QwakModules>();
+    if !modules.is_installed(&module) {
+        error!("the server's qwak module \"{module}\" is not installed");
+        return;
+    }
+    let args = LaunchArgs {
+        qwak: Some(module),
+        mutators,
+        connect: world.get_resource::<ServerAddress>().map(|a| a.0.clone()),
+        ..world.resource::<LaunchArgs>().clone()
+    };
+    error_return!(modules.relaunch(&args));
+    world.send_event(AppExit::Success);
+}
+
 pub fn init_client(
     world: &mut World,
     next_state: &mut NextState<NetState>,
@@ -275,6 +447,7 @@ pub fn init_client(
     steam_client: &Option<Res<SteamClient>>,
 ) -> bool {
     info!("joining: {ip}");
+    world.remove_resource::<DisconnectReason>();
     let client = RenetClient::new(connection_config());
 
     if let Some(sc) = steam_client {
@@ -294,11 +467,21 @@ pub fn init_client(
 
         let client_id = current_time.as_micros() as u64;
 
+        let user_data = match world.resource::<ConnectInfo>().user_data() {
+            Ok(user_data) => user_data,
+            Err(e) => {
+                error!("failed to encode the connect info: {e}");
+                world.insert_resource(DisconnectReason(
+                    "The names of your qwak module and plugins are too long to connect".to_string(),
+                ));
+                return false;
+            }
+        };
         let authentication = ClientAuthentication::Unsecure {
             client_id,
             protocol_id: PROTOCOL_ID,
             server_addr,
-            user_data: None,
+            user_data: Some(user_data),
         };
 
         let transport = error_return!(NetcodeClientTransport::new(
@@ -311,6 +494,7 @@ pub fn init_client(
         world.insert_resource(CurrentClientId(client_id));
     }
     world.insert_resource(client);
+    world.insert_resource(ServerAddress(ip.clone()));
     world.insert_resource(Lobby::default());
     next_state.set(NetState::Client);
     info!("started client");
@@ -322,6 +506,7 @@ fn clean_up(mut commands: Commands) {
     commands.remove_resource::<SteamClientTransport>();
     commands.remove_resource::<RenetClient>();
     commands.remove_resource::<Lobby>();
+    commands.remove_resource::<ServerAddress>();
 }
 
 pub fn system_cleanup() -> SystemConfigs {
@@ -329,7 +514,11 @@ pub fn system_cleanup() -> SystemConfigs {
 }
 
 pub fn systems() -> SystemConfigs {
-    (get_events,).into_configs()
+    (
+        get_events,
+        send_handshake.run_if(resource_exists::<SteamClientTransport>),
+    )
+        .into_configs()
 }
 pub fn all_cons() -> SystemConfigs {
     (handle_messages,).into_configs()
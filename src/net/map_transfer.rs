@@ -0,0 +1,304 @@
+use super::{ClientChannel, ClientMessage, DisconnectReason, NetState, ServerChannel};
+use bevy::prelude::*;
+use bevy_renet::renet::{RenetClient, RenetServer};
+use faststr::FastStr;
+use macros::error_return;
+use resources::{CurrentMap, CurrentStage, MapLoadingProgress};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::File,
+    io::Write,
+    path::{Component, Path, PathBuf},
+};
+
+/// The size of the file chunks sent over [ServerChannel::MapTransfer].
+const CHUNK_SIZE: usize = 64 * 1024;
+/// Where downloaded maps are stored, in a folder named after the map hash.
+const CACHE_DIR: &str = "assets/cache/maps";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum MapTransferMessage {
+    /// The files which will be sent, paths are relative to the map folder.
+    Start { files: Vec<(FastStr, u64)> },
+    /// The next part of the current file.
+    Chunk(Vec<u8>),
+    /// All files have been sent.
+    End,
+}
+impl MapTransferMessage {
+    pub fn bytes(&self) -> Result<Vec<u8>, std::boxed::Box<bincode::ErrorKind>> {
+        bincode::serialize(self)
+    }
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Box<bincode::ErrorKind>> {
+        bincode::deserialize(bytes)
+    }
+}
+
+/// Returns the hash the server sends together with a map.
+/// It covers the textures as well, so a cached map is only used with the textures it came with.
+pub fn map_hash(map: &Path) -> std::io::Result<String> {
+    let files = map_files(map)?
+        .iter()
+        .map(|(name, data)| format!("{name} {}\n", integrity::hash_bytes(data)))
+        .collect::<String>();
+    Ok(integrity::hash_bytes(files.as_bytes()))
+}
+
+/// Reads `map` and the textures it uses, with their paths relative to the map folder.
+fn map_files(map: &Path) -> std::io::Result<Vec<(FastStr, Vec<u8>)>> {
+    let content = vfs::read_to_string(map)?;
+    let mut textures = map_parser::parse(&content)?
+        .into_iter()
+        .flat_map(|e| e.brushes)
+        .flatten()
+        .map(|p| p.texture)
+        .collect::<Vec<_>>();
+    textures.sort();
+    textures.dedup();
+
+    let name = map
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or("map.map".to_string());
+    let mut files = vec![(FastStr::from(name), content.into_bytes())];
+    for texture in textures {
+        // Textures missing on the server are missing for everyone
+        let Ok(data) = vfs::read(texture_path(map, &texture)) else {
+            continue;
+        };
+        files.push((FastStr::from(format!("textures/{texture}.png")), data));
+    }
+    Ok(files)
+}
+
+/// Returns true if `hash` looks like one made by [map_hash].
+/// It is used as a folder name, so anything else could point outside of the cache.
+pub fn valid_hash(hash: &str) -> bool {
+    hash.len() == 64 && hash.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+/// Looks for a local copy of `map` with the given hash,
+/// either at the same path as the server or in the download cache.
+pub fn find_map(map: &Path, hash: &str) -> Option<PathBuf> {
+    if !valid_hash(hash) {
+        return None;
+    }
+    let cached = cached_map(map, hash);
+    [map.to_path_buf(), cached]
+        .into_iter()
+        .find(|path| map_hash(path).is_ok_and(|h| h == hash))
+}
+
+fn cached_map(map: &Path, hash: &str) -> PathBuf {
+    let name = map.file_name().unwrap_or("map.map".as_ref());
+    Path::new(CACHE_DIR).join(hash).join(name)
+}
+
+/// Returns the asset path of a texture used by `map`.
+/// Textures next to the map, like the ones of a downloaded map, are used over the installed ones.
+pub fn texture_path(map: &Path, texture: &str) -> String {
+    let local = map.with_file_name(format!("textures/{texture}.png"));
+    match local.strip_prefix("assets") {
+        Ok(path) if vfs::get().exists(path) => path.to_string_lossy().into_owned(),
+        _ => format!("textures/{texture}.png"),
+    }
+}
+
+/// The messages which are waiting to be sent to each client.
+#[derive(Debug, Resource, Default)]
+pub struct MapTransfers(HashMap<u64, VecDeque<Vec<u8>>>);
+
+/// Queues the current map and its textures to be sent to a client.
+pub fn queue_map(world: &mut World, client_id: u64) {
+    let map = world.resource::<CurrentMap>().0.clone();
+    let files = error_return!(map_files(&map));
+
+    info!("sending map {map:?} ({} files) to {client_id}", files.len());
+    let mut queue = VecDeque::new();
+    let start = MapTransferMessage::Start {
+        files: files
+            .iter()
+            .map(|(name, data)| (name.clone(), data.len() as u64))
+            .collect(),
+    };
+    queue.push_back(error_return!(start.bytes()));
+    for (_, data) in files {
+        for chunk in data.chunks(CHUNK_SIZE) {
+            queue.push_back(error_return!(
+                MapTransferMessage::Chunk(chunk.to_vec()).bytes()
+            ));
+        }
+    }
+    queue.push_back(error_return!(MapTransferMessage::End.bytes()));
+
+    world
+        .resource_mut::<MapTransfers>()
+        .0
+        .insert(client_id, queue);
+}
+
+/// Sends as many queued chunks as the channel has room for.
+pub fn send_map_transfers(mut server: ResMut<RenetServer>, mut transfers: ResMut<MapTransfers>) {
+    transfers
+        .0
+        .retain(|client_id, _| server.is_connected(*client_id));
+    for (client_id, queue) in &mut transfers.0 {
+        while let Some(message) = queue.front() {
+            let channel = ServerChannel::MapTransfer as u8;
+            if !server.can_send_message(*client_id, channel, message.len()) {
+                break;
+            }
+            server.send_message(*client_id, channel, queue.pop_front().unwrap_or_default());
+        }
+    }
+    transfers.0.retain(|_, queue| !queue.is_empty());
+}
+
+/// A map which is being downloaded from the server.
+#[derive(Debug, Resource)]
+pub struct MapDownload {
+    map: PathBuf,
+    hash: String,
+    requested: bool,
+    files: VecDeque<(FastStr, u64)>,
+    file: Option<(File, u64)>,
+}
+impl MapDownload {
+    /// Returns `None` if the hash is not one made by [map_hash].
+    pub fn new(map: PathBuf, hash: String) -> Option<Self> {
+        valid_hash(&hash).then(|| Self {
+            map,
+            hash,
+            requested: false,
+            files: VecDeque::new(),
+            file: None,
+        })
+    }
+
+    /// Opens the next file to write to, skipping empty ones.
+    fn next_file(&mut self, dir: &Path) -> std::io::Result<()> {
+        self.file = None;
+        while let Some((name, size)) = self.files.pop_front() {
+            // Never let the server write outside of the cache
+            if !Path::new(&*name)
+                .components()
+                .all(|c| matches!(c, Component::Normal(_)))
+            {
+                return Err(std::io::Error::other(format!("invalid file name: {name}")));
+            }
+            let path = dir.join(&*name);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let file = File::create(path)?;
+            if size > 0 {
+                self.file = Some((file, size));
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Handles the received messages, returning the map once it is done.
+    fn receive(
+        &mut self,
+        client: &mut RenetClient,
+        progress: &mut MapLoadingProgress,
+    ) -> std::io::Result<Option<PathBuf>> {
+        if !self.requested {
+            info!("downloading map {:?} from the server", self.map);
+            self.requested = true;
+            let request = ClientMessage::RequestMap
+                .bytes()
+                .map_err(std::io::Error::other)?;
+            client.send_message(ClientChannel::Command as u8, request);
+        }
+
+        let cached = cached_map(&self.map, &self.hash);
+        let dir = cached
+            .parent()
+            .unwrap_or(Path::new(CACHE_DIR))
+            .to_path_buf();
+        // The cache is inside of the running qwak module's assets
+        let dir = vfs::get()
+            .disk_path(dir)
+            .ok_or_else(|| std::io::Error::other("the map cache is not on disk"))?;
+        while let Some(message) = client.receive_message(ServerChannel::MapTransfer as u8) {
+            match MapTransferMessage::from_bytes(&message).map_err(std::io::Error::other)? {
+                MapTransferMessage::Start { files } => {
+                    *progress = MapLoadingProgress {
+                        done: 0,
+                        total: files.iter().map(|(_, size)| *size as usize).sum(),
+                    };
+                    self.files = files.into();
+                    self.next_file(&dir)?;
+                }
+                MapTransferMessage::Chunk(data) => {
+                    let Some((file, remaining)) = &mut self.file else {
+                        return Err(std::io::Error::other("got a chunk without a file"));
+                    };
+                    file.write_all(&data)?;
+                    *remaining = remaining.saturating_sub(data.len() as u64);
+                    progress.done += data.len();
+                    if *remaining == 0 {
+                        self.next_file(&dir)?;
+                    }
+                }
+                MapTransferMessage::End => {
+                    if !map_hash(&cached).is_ok_and(|h| h == self.hash) {
+                        return Err(std::io::Error::other(
+                            "the map or its textures do not match the server",
+                        ));
+                    }
+                    return Ok(Some(cached));
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Requests the map from the server and writes the received files to the cache.
+/// Leaves the server if the download fails, as the game can't start without the map.
+pub fn receive_map_transfer(
+    mut commands: Commands,
+    mut client: ResMut<RenetClient>,
+    mut download: ResMut<MapDownload>,
+    mut current_map: ResMut<CurrentMap>,
+    mut progress: ResMut<MapLoadingProgress>,
+    mut net_state: ResMut<NextState<NetState>>,
+    mut stage: ResMut<NextState<CurrentStage>>,
+) {
+    match download.receive(&mut client, &mut progress) {
+        Ok(None) => {}
+        Ok(Some(map)) => {
+            info!("done downloading map to {map:?}");
+            current_map.0 = map;
+            commands.remove_resource::<MapDownload>();
+        }
+        Err(e) => {
+            error!("failed downloading map {:?}: {e}", download.map);
+            commands.remove_resource::<MapDownload>();
+            commands.insert_resource(DisconnectReason(format!("failed downloading the map: {e}")));
+            client.disconnect();
+            net_state.set(NetState::Offline);
+            stage.set(CurrentStage::MainMenu);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_sha256_hashes_are_valid() {
+        assert!(valid_hash(&"0123456789abcdef".repeat(4)));
+        assert!(!valid_hash(&"0123456789ABCDEF".repeat(4)));
+        assert!(!valid_hash("../../.."));
+        assert!(!valid_hash(&format!("/{}", "a".repeat(63))));
+        assert!(!valid_hash(&"a".repeat(65)));
+        assert!(!valid_hash(""));
+    }
+}
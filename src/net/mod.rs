@@ -1,9 +1,11 @@
-use crate::queries::NetWorld;
+use crate::{queries::NetWorld, qwak_modules::QwakModules, qwaks::Qwaks};
 use bevy::prelude::*;
-use bevy_renet::renet::*;
+use bevy_renet::{netcode::NETCODE_USER_DATA_BYTES, renet::*};
 use faststr::FastStr;
+use integrity::Manifest;
 use macros::{error_return, option_return};
-use qwak_helper_types::{MapInteraction, PickupData};
+use qwak_helper_types::{HudWidget, MapInteraction, Menu, PickupData, PickupType};
+use resources::map_settings::MapSettings;
 use serde::{Deserialize, Serialize};
 use std::{path::PathBuf, time::Duration};
 
@@ -11,6 +13,7 @@ use self::steam::SteamClient;
 
 pub mod client;
 mod lobby;
+pub mod map_transfer;
 pub mod server;
 pub mod steam;
 pub use lobby::*;
@@ -35,12 +38,20 @@ pub fn update_world(client_id: u64, message: &ClientMessage, nw: &mut NetWorld)
                     .x = *cam_rot;
             }
         }
-        ClientMessage::PickupWeapon { weapon } => {
+        ClientMessage::Pickup { pickup_type, gives } => {
             let player = option_return!(nw.lobby.get(&client_id)).entity;
 
             let (player_ent, mut player, _) = error_return!(nw.players.get_mut(player));
 
-            if let Some(weapon_data) = nw.weapon_map.0.get(weapon) {
+            if !matches!(pickup_type, PickupType::Weapon) {
+                let message = player.add_pickup(pickup_type, gives);
+                if player.id == nw.current_id.0 {
+                    player.display_message(&mut nw.commands, &nw.asset_server, message);
+                }
+                return;
+            }
+
+            if let Some(weapon_data) = nw.weapon_map.0.get(gives) {
                 let slot = weapon_data.slot;
                 let handle = nw
                     .asset_server
@@ -78,7 +89,7 @@ pub fn update_world(client_id: u64, message: &ClientMessage, nw: &mut NetWorld)
                     }
                 }
             } else {
-                error!("tried to pickup nonexisting weapon: \"{weapon}\"")
+                error!("tried to pickup nonexisting weapon: \"{gives}\"")
             }
         }
         ClientMessage::WeaponAnim { anim } => {
@@ -142,6 +153,101 @@ pub enum Connections {
 #[derive(Debug, Resource)]
 pub struct IsSteam;
 
+/// The hash of every file in `assets`, sent to clients with different assets.
+#[derive(Debug, Resource, Clone, Default)]
+pub struct AssetManifest(pub Manifest);
+impl AssetManifest {
+    pub fn load() -> Self {
+        match integrity::get_asset_manifest() {
+            Ok(manifest) => {
+                info!("running with asset hash: {}", manifest.hash());
+                Self(manifest)
+            }
+            Err(e) => {
+                error!("failed to hash assets: {e}");
+                Self::default()
+            }
+        }
+    }
+}
+
+/// What a client has to share with the server to be allowed to play.
+/// Sent as the netcode user data, or as [ClientMessage::Handshake] over Steam.
+#[derive(Debug, Resource, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConnectInfo {
+    pub asset_hash: String,
+    pub qwak_module: String,
+    pub qwak_name: String,
+    pub qwak_version: [i32; 3],
+    /// Left out of the netcode user data, which only has room for [ConnectInfo::mutator_hash].
+    pub mutators: Vec<String>,
+    /// The hash of the mutators, which is what the server compares.
+    pub mutator_hash: String,
+}
+impl ConnectInfo {
+    pub fn new(qwaks: &Qwaks, modules: &QwakModules, manifest: &AssetManifest) -> Self {
+        Self {
+            asset_hash: manifest.0.hash(),
+            qwak_module: modules.active.name.clone(),
+            qwak_name: qwaks.plugin_name(),
+            qwak_version: qwaks.plugin_version(),
+            mutator_hash: integrity::hash_bytes(qwaks.mutators().join("\n").as_bytes()),
+            mutators: qwaks.mutators(),
+        }
+    }
+
+    /// Fails if the module or plugin name are too long to fit.
+    pub fn user_data(&self) -> Result<[u8; NETCODE_USER_DATA_BYTES], Box<bincode::ErrorKind>> {
+        let mut data = [0; NETCODE_USER_DATA_BYTES];
+        let compact = Self {
+            mutators: Vec::new(),
+            ..self.clone()
+        };
+        bincode::serialize_into(&mut data[..], &compact)?;
+        Ok(data)
+    }
+    pub fn from_user_data(data: &[u8]) -> Result<Self, Box<bincode::ErrorKind>> {
+        bincode::deserialize(data)
+    }
+
+    /// Returns why a client with `other` can't join a server with `self`.
+    pub fn mismatch(&self, other: &Self) -> Option<String> {
+        let version = |v: [i32; 3]| format!("{}.{}.{}", v[0], v[1], v[2]);
+        if self.qwak_module != other.qwak_module {
+            Some(format!(
+                "The server is running the qwak module \"{}\", but you are running \"{}\"",
+                self.qwak_module, other.qwak_module
+            ))
+        } else if self.qwak_name != other.qwak_name || self.qwak_version != other.qwak_version {
+            Some(format!(
+                "The server is running \"{}\" ({}), but you are running \"{}\" ({})",
+                self.qwak_name,
+                version(self.qwak_version),
+                other.qwak_name,
+                version(other.qwak_version),
+            ))
+        } else if self.mutator_hash != other.mutator_hash {
+            // Only the hash of the client's mutators is known without a handshake
+            Some(format!(
+                "The server is running the mutators [{}], but you are running different ones",
+                self.mutators.join(", "),
+            ))
+        } else if self.asset_hash != other.asset_hash {
+            Some("Your assets differ from the server's".to_string())
+        } else {
+            None
+        }
+    }
+}
+
+/// Why we were last disconnected from a server, shown in the main menu.
+#[derive(Debug, Resource)]
+pub struct DisconnectReason(pub String);
+
+/// The address of the server we are connected to.
+#[derive(Debug, Resource)]
+pub struct ServerAddress(pub String);
+
 #[derive(Debug, States, PartialEq, Eq, Hash, Clone, Copy, Default)]
 pub enum NetState {
     #[default]
@@ -154,6 +260,8 @@ pub enum NetState {
 pub enum ClientMessage {
     RequestRespawn,
     RequestLobbyInfo,
+    /// Asks the server to send the current map, when the client doesn't have it.
+    RequestMap,
 
     UpdatePosition {
         position: Vec3,
@@ -161,13 +269,17 @@ pub enum ClientMessage {
         cam_rot: f32,
     },
 
-    PickupWeapon {
-        weapon: FastStr,
+    /// Sent by the server when a player picks something up, refused when a client sends it.
+    Pickup {
+        pickup_type: PickupType,
+        gives: FastStr,
     },
 
     Fire {
         attack: usize,
     },
+    /// The player started reloading their weapon.
+    Reload,
 
     SwitchWeapon {
         slot: usize,
@@ -179,6 +291,24 @@ pub enum ClientMessage {
     },
 
     Interact,
+
+    /// A chat message from the player.
+    Chat {
+        text: String,
+    },
+    /// A command registered by a plugin, without the `/`.
+    Command {
+        line: String,
+    },
+
+    /// The player pressed a button in a menu opened by a plugin.
+    MenuSelect {
+        menu: FastStr,
+        choice: FastStr,
+    },
+
+    /// Sent by Steam clients when connecting, as Steam has no netcode user data.
+    Handshake(ConnectInfo),
 }
 impl ClientMessage {
     pub fn bytes(&self) -> Result<Vec<u8>, std::boxed::Box<bincode::ErrorKind>> {
@@ -194,7 +324,8 @@ pub enum SimulationEvent {
     PlayerPicksUpPickup {
         id: u64,
         player: u64,
-        pickup: FastStr,
+        /// The key of the pickup in the [PickupMap](resources::PickupMap).
+        classname: FastStr,
     },
 }
 
@@ -208,7 +339,24 @@ pub enum ServerMessage {
         armor: f32,
         health: f32,
     },
-    SetMap(PathBuf),
+    /// The server is about to disconnect the client.
+    Disconnect {
+        reason: String,
+        /// The server's assets, if they are the reason.
+        manifest: Option<Manifest>,
+        /// The server's qwak module, if it or the mutators are the reason.
+        module: Option<String>,
+        /// The server's mutators, to relaunch with along with `module`.
+        mutators: Vec<String>,
+    },
+    SetMap {
+        map: PathBuf,
+        hash: String,
+        settings: MapSettings,
+    },
+    ReloadMap {
+        textures: Vec<FastStr>,
+    },
     LobbyInfo(FastStr),
     TranslateBrush {
         target: FastStr,
@@ -269,6 +417,35 @@ pub enum ServerMessage {
         death: u64,
         hurter: Option<u64>,
     },
+    /// A projectile spawned by a plugin.
+    SpawnProjectile {
+        id: u64,
+        translation: Vec3,
+        dir: Vec3,
+        projectile: FastStr,
+    },
+    /// A model placed by a plugin.
+    SpawnProp {
+        id: u64,
+        translation: Vec3,
+        scale: f32,
+        model: FastStr,
+    },
+    /// Removes an entity spawned by a plugin, or a pickup.
+    DespawnEntity {
+        id: u64,
+    },
+    /// Creates, replaces or removes (if `widget` is `None`) a HUD widget made by a plugin.
+    SetHud {
+        name: FastStr,
+        widget: Option<HudWidget>,
+        global: bool,
+    },
+    /// Opens, replaces or closes (if `menu` is `None`) a menu made by a plugin.
+    OpenMenu {
+        name: FastStr,
+        menu: Option<Menu>,
+    },
 }
 impl ServerMessage {
     pub fn bytes(&self) -> Result<Vec<u8>, std::boxed::Box<bincode::ErrorKind>> {
@@ -294,6 +471,7 @@ pub enum ClientChannel {
 pub enum ServerChannel {
     ServerMessages,
     NetworkedEntities,
+    MapTransfer,
 }
 
 impl ClientChannel {
@@ -331,6 +509,13 @@ impl ServerChannel {
                     resend_time: Duration::from_millis(200),
                 },
             },
+            ChannelConfig {
+                channel_id: Self::MapTransfer as u8,
+                max_memory_usage_bytes: 2 * 1024 * 1024,
+                send_type: SendType::ReliableOrdered {
+                    resend_time: Duration::from_millis(200),
+                },
+            },
         ]
     }
 }
@@ -3,20 +3,28 @@ use super::{
     connection_config, update_world,
 };
 use crate::{
-    net::{CurrentClientId, IsSteam, Lobby, PlayerInfo, ServerChannel, ServerMessage},
+    entities::{PluginEntity, PropEntity},
+    map_gen::map_settings::read_map_settings,
+    net::{
+        AssetManifest, ConnectInfo, CurrentClientId, IsSteam, Lobby, PlayerInfo, ServerChannel,
+        ServerMessage, map_transfer,
+    },
     player::Player,
     queries::NetWorld,
+    qwaks::QwakTicker,
+    ui::hud::{HudElement, HudTimer},
 };
 use bevy::{
     ecs::{
         event::EventReader,
         schedule::{IntoSystemConfigs, SystemConfigs, common_conditions::resource_exists},
-        system::{Res, ResMut},
+        system::{Query, Res, ResMut},
         world::World,
     },
     hierarchy::DespawnRecursiveExt,
-    log::{error, info},
-    prelude::{EventWriter, NextState},
+    log::{error, info, warn},
+    prelude::{Commands, EventWriter, Mut, NextState, Resource, not},
+    time::{Time, Timer, TimerMode},
 };
 use bevy_renet::{
     netcode::{NetcodeServerTransport, NetcodeTransportError, ServerAuthentication, ServerConfig},
@@ -24,66 +32,42 @@ use bevy_renet::{
     steam::SteamTransportError,
 };
 use faststr::FastStr;
-use macros::{error_continue, error_return, option_return};
-use qwak_helper_types::{Attack, MapInteraction, PlayerKilled, PlayerLeave};
+use integrity::Manifest;
+use macros::{error_continue, error_return, option_continue, option_return};
+use qwak_helper_types::{Attack, MapInteraction, MenuSelection, PlayerKilled, PlayerLeave};
 use renet_steam::{AccessPermission, SteamServerConfig, SteamServerTransport};
-use resources::{CurrentMap, MapFirstRun};
-use std::{net::UdpSocket, time::SystemTime};
+use resources::{
+    ChangeMap, ChangingMap, CurrentMap, MapFirstRun, MapRotation, if_map_done_loading,
+    map_settings::MapSettings,
+};
+use std::{collections::HashMap, net::UdpSocket, time::SystemTime};
 use steamworks::SteamId;
 
-// Yea this is cursed, I know, but somehow these references need to be passed to
-// host functions, and I don't feel like creating an EDSL, or passing them through
-// the WASM plugin. I promise that this should never segfault :)
-pub static mut NW_PTR: Option<(
-    &mut NetWorld,
-    &mut RenetServer,
-    &mut EventWriter<ServerMessage>,
-)> = None;
-/// Returns the content of [NW_PTR]
-#[macro_export]
-macro_rules! get_nw {
-    () => {{
-        // println!("maybe here: {}:{}:{}", file!(), line!(), column!());
-        #[allow(unsafe_code)]
-        unsafe {
-            #[allow(unsafe_code)]
-            let nw = NW_PTR.as_ref();
-            let r: *const _ = nw.unwrap_unchecked();
-            std::ptr::read(r)
-        }
-    }};
-}
-/// Setsthe content of [NW_PTR]
-#[macro_export]
-macro_rules! set_nw {
-    ($nw:expr,$server:expr, $server_events:expr) => {
-        #[allow(
-            clippy::missing_transmute_annotations,
-            unsafe_code,
-            clippy::macro_metavars_in_unsafe
-        )]
-        unsafe {
-            NW_PTR = Some(std::mem::transmute::<
-                (&NetWorld, &RenetServer, &EventWriter<ServerMessage>),
-                _,
-            >((&*$nw, &*$server, &*$server_events)))
-        };
-    };
-}
-
-pub fn transmit_message(server: &mut RenetServer, nw: &mut NetWorld, text: String) {
+/// Shows a message to the local player.
+pub fn display_message(nw: &mut NetWorld, text: String) {
     for (_, player, _) in &nw.players {
         if player.id == nw.current_id.0 {
-            player.display_message(&mut nw.commands, &nw.asset_server, text.clone());
+            player.display_message(&mut nw.commands, &nw.asset_server, text);
             break;
         }
     }
+}
+
+pub fn transmit_message(server: &mut RenetServer, nw: &mut NetWorld, text: String) {
+    display_message(nw, text.clone());
     server.broadcast_message(
         ServerChannel::ServerMessages as u8,
         error_return!(ServerMessage::Message { text }.bytes()),
     );
 }
 
+/// Tells everyone about qwak calls which have failed.
+fn report_qwak_faults(mut server: ResMut<RenetServer>, mut nw: NetWorld) {
+    for fault in nw.plugins.take_faults() {
+        transmit_message(&mut server, &mut nw, fault);
+    }
+}
+
 fn frag_checker(
     mut server: ResMut<RenetServer>,
     mut nw: NetWorld,
@@ -125,17 +109,128 @@ fn frag_checker(
             ),
         );
 
-        set_nw!(&nw, &server, &event_writer);
         let info = PlayerKilled {
             player_id: id,
             by_id: Some(hurter),
         };
-        error_continue!(nw.plugins.default.map_player_killed(info));
-        // error_continue!(nw.plugins.default.map_player_respawn(info));
+        nw.plugins.map_player_killed(info);
+        // nw.plugins.map_player_respawn(info);
+    }
+}
+
+/// Clients which have been told why they are being disconnected,
+/// and are disconnected once the message had time to arrive.
+#[derive(Debug, Resource, Default)]
+pub struct PendingKicks(Vec<(u64, Timer)>);
+impl PendingKicks {
+    pub fn kick(
+        &mut self,
+        server: &mut RenetServer,
+        client_id: u64,
+        reason: String,
+        manifest: Option<Manifest>,
+        module: Option<(String, Vec<String>)>,
+    ) {
+        warn!("kicking {client_id}: {reason}");
+        let (module, mutators) = module.unzip();
+        let message = ServerMessage::Disconnect {
+            reason,
+            manifest,
+            module,
+            mutators: mutators.unwrap_or_default(),
+        };
+        server.send_message(
+            client_id,
+            ServerChannel::ServerMessages as u8,
+            error_return!(message.bytes()),
+        );
+        self.0
+            .push((client_id, Timer::from_seconds(1.0, TimerMode::Once)));
+    }
+}
+
+fn process_kicks(
+    time: Res<Time>,
+    mut server: ResMut<RenetServer>,
+    mut kicks: ResMut<PendingKicks>,
+) {
+    kicks.0.retain_mut(|(client_id, timer)| {
+        if !timer.tick(time.delta()).finished() {
+            return true;
+        }
+        server.disconnect(*client_id);
+        false
+    });
+}
+
+/// Kicks a client whose [ConnectInfo] does not match the server's.
+/// Returns true if the client was kicked.
+fn verify_client(
+    kicks: &mut PendingKicks,
+    server: &mut RenetServer,
+    client_id: u64,
+    ours: &ConnectInfo,
+    theirs: &ConnectInfo,
+    manifest: &AssetManifest,
+) -> bool {
+    let Some(reason) = ours.mismatch(theirs) else {
+        return false;
+    };
+    // Lets the client find out which files differ
+    let manifest = (ours.asset_hash != theirs.asset_hash).then(|| manifest.0.clone());
+    // Lets the client restart with the server's module and mutators
+    let module = (ours.qwak_module != theirs.qwak_module
+        || ours.mutator_hash != theirs.mutator_hash)
+        .then(|| (ours.qwak_module.clone(), ours.mutators.clone()));
+    kicks.kick(server, client_id, reason, manifest, module);
+    true
+}
+
+/// How long Steam clients have to send their [ClientMessage::Handshake].
+const HANDSHAKE_TIMEOUT: f32 = 10.0;
+
+/// Steam clients which have connected without sending a valid [ClientMessage::Handshake] yet.
+/// They are kept out of the lobby until they do, and kicked if it takes too long.
+#[derive(Debug, Resource, Default)]
+pub struct UnverifiedClients {
+    waiting: HashMap<u64, Timer>,
+    /// Clients whose handshake was accepted, which join the lobby on the next update.
+    verified: Vec<u64>,
+}
+impl UnverifiedClients {
+    fn wait_for(&mut self, client_id: u64) {
+        let timer = Timer::from_seconds(HANDSHAKE_TIMEOUT, TimerMode::Once);
+        self.waiting.insert(client_id, timer);
+    }
+
+    fn verify(&mut self, client_id: u64) {
+        if self.waiting.remove(&client_id).is_some() {
+            self.verified.push(client_id);
+        }
+    }
+
+    fn is_waiting(&self, client_id: u64) -> bool {
+        self.waiting.contains_key(&client_id)
     }
 }
 
-#[allow(clippy::type_complexity)]
+fn process_handshakes(
+    time: Res<Time>,
+    mut server: ResMut<RenetServer>,
+    mut kicks: ResMut<PendingKicks>,
+    mut unverified: ResMut<UnverifiedClients>,
+) {
+    unverified.waiting.retain(|client_id, timer| {
+        if !timer.tick(time.delta()).finished() {
+            return true;
+        }
+        let reason = "No handshake received".to_string();
+        kicks.kick(&mut server, *client_id, reason, None, None);
+        false
+    });
+}
+
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
 pub fn server_events(
     mut events: EventReader<ServerEvent>,
     mut connections: EventWriter<Connections>,
@@ -144,84 +239,45 @@ pub fn server_events(
 
     steam: Option<Res<SteamClient>>,
     map: Res<CurrentMap>,
+    map_settings: Res<MapSettings>,
+    connect_info: Res<ConnectInfo>,
+    manifest: Res<AssetManifest>,
+    transport: Option<Res<NetcodeServerTransport>>,
+    mut kicks: ResMut<PendingKicks>,
+    mut unverified: ResMut<UnverifiedClients>,
+    props: Query<(&PluginEntity, &PropEntity)>,
+    hud: Query<(&HudElement, Option<&HudTimer>)>,
     mut nw: NetWorld,
 ) {
+    let mut joined = Vec::new();
     // Handle connection details
     for event in events.read() {
         match event {
             ServerEvent::ClientConnected { client_id } => {
-                connections.send(Connections::Join(*client_id));
-
-                server.send_message(
-                    *client_id,
-                    ServerChannel::ServerMessages as u8,
-                    error_return!(ServerMessage::SetMap(map.0.clone()).bytes()),
-                );
-
-                for (pickup, trans) in &nw.pickups_query {
-                    server.send_message(
-                        *client_id,
-                        ServerChannel::ServerMessages as u8,
-                        error_continue!(
-                            ServerMessage::SpawnPickup {
-                                id: pickup.id,
-                                translation: trans.translation,
-                                data: pickup.data.clone()
-                            }
-                            .bytes()
-                        ),
-                    )
-                }
-
-                // Spawn players for newly joined client
-                for (other_id, info) in &nw.lobby {
-                    let (_, pl, trans) = error_continue!(nw.players.get(info.entity));
-                    server.send_message(
+                if let Some(data) = transport.as_ref().and_then(|t| t.user_data(*client_id)) {
+                    let Ok(info) = ConnectInfo::from_user_data(&data) else {
+                        let reason = "Invalid connection info".to_string();
+                        kicks.kick(&mut server, *client_id, reason, None, None);
+                        continue;
+                    };
+                    if verify_client(
+                        &mut kicks,
+                        &mut server,
                         *client_id,
-                        ServerChannel::ServerMessages as u8,
-                        error_continue!(
-                            ServerMessage::SpawnPlayer {
-                                name: info.name.clone(),
-                                id: *other_id,
-                                translation: trans.translation,
-                                weapons: pl
-                                    .weapons
-                                    .iter()
-                                    .map(|v| v.iter().map(|w| w.data.id.clone()).collect())
-                                    .collect()
-                            }
-                            .bytes()
-                        ),
-                    );
+                        &connect_info,
+                        &info,
+                        &manifest,
+                    ) {
+                        continue;
+                    }
+                    joined.push(*client_id);
+                } else {
+                    // Steam clients send their info in a handshake instead
+                    unverified.wait_for(*client_id);
                 }
-
-                let spawn_point = nw.player_spawn.0;
-                let entity =
-                    Player::spawn(&mut nw, false, spawn_point, *client_id, Vec::new(), None);
-                let name = FastStr::from(
-                    steam
-                        .as_ref()
-                        .map(|s| s.friends().get_friend(SteamId::from_raw(*client_id)))
-                        .map(|f| f.name())
-                        .unwrap_or(format!("{client_id}")),
-                );
-                nw.lobby
-                    .insert(*client_id, PlayerInfo::new(entity, name.clone()));
-
-                server.broadcast_message(
-                    ServerChannel::ServerMessages as u8,
-                    error_continue!(
-                        ServerMessage::SpawnPlayer {
-                            id: *client_id,
-                            translation: spawn_point,
-                            weapons: Vec::new(),
-                            name
-                        }
-                        .bytes()
-                    ),
-                )
             }
             ServerEvent::ClientDisconnected { client_id, reason } => {
+                unverified.waiting.remove(client_id);
                 if let Some(player_info) = nw.lobby.remove(client_id) {
                     connections.send(Connections::Leave(*client_id, format!("{reason}")));
                     nw.commands.entity(player_info.entity).despawn_recursive();
@@ -235,16 +291,148 @@ pub fn server_events(
         }
     }
 
+    let verified = std::mem::take(&mut unverified.verified);
+    for client_id in joined.into_iter().chain(verified) {
+        // The client might have left while waiting
+        if !server.is_connected(client_id) {
+            continue;
+        }
+        connections.send(Connections::Join(client_id));
+
+        server.send_message(
+            client_id,
+            ServerChannel::ServerMessages as u8,
+            error_return!(
+                ServerMessage::SetMap {
+                    map: map.0.clone(),
+                    hash: error_return!(map_transfer::map_hash(&map.0)),
+                    settings: map_settings.clone(),
+                }
+                .bytes()
+            ),
+        );
+
+        for (pickup, trans) in &nw.pickups_query {
+            server.send_message(
+                client_id,
+                ServerChannel::ServerMessages as u8,
+                error_continue!(
+                    ServerMessage::SpawnPickup {
+                        id: pickup.id,
+                        translation: trans.translation,
+                        data: pickup.data.clone()
+                    }
+                    .bytes()
+                ),
+            )
+        }
+
+        for (entity, prop) in &props {
+            server.send_message(
+                client_id,
+                ServerChannel::ServerMessages as u8,
+                error_continue!(
+                    ServerMessage::SpawnProp {
+                        id: entity.0,
+                        translation: prop.translation,
+                        scale: prop.scale,
+                        model: prop.model.clone(),
+                    }
+                    .bytes()
+                ),
+            )
+        }
+
+        for (element, timer) in hud.iter().filter(|(e, _)| e.global) {
+            server.send_message(
+                client_id,
+                ServerChannel::ServerMessages as u8,
+                error_continue!(
+                    ServerMessage::SetHud {
+                        name: element.name.clone(),
+                        // Timers carry on where they are instead of restarting
+                        widget: Some(element.current_widget(timer)),
+                        global: true,
+                    }
+                    .bytes()
+                ),
+            )
+        }
+
+        // Spawn players for newly joined client
+        for (other_id, info) in &nw.lobby {
+            let (_, pl, trans) = error_continue!(nw.players.get(info.entity));
+            server.send_message(
+                client_id,
+                ServerChannel::ServerMessages as u8,
+                error_continue!(
+                    ServerMessage::SpawnPlayer {
+                        name: info.name.clone(),
+                        id: *other_id,
+                        translation: trans.translation,
+                        weapons: pl
+                            .weapons
+                            .iter()
+                            .map(|v| v.iter().map(|w| w.data.id.clone()).collect())
+                            .collect()
+                    }
+                    .bytes()
+                ),
+            );
+        }
+
+        let spawn_point = nw.player_spawn.0;
+        let entity = Player::spawn(
+            &mut nw,
+            false,
+            spawn_point,
+            client_id,
+            Vec::new(),
+            None,
+            false,
+        );
+        let name = FastStr::from(
+            steam
+                .as_ref()
+                .map(|s| s.friends().get_friend(SteamId::from_raw(client_id)))
+                .map(|f| f.name())
+                .unwrap_or(format!("{client_id}")),
+        );
+        nw.lobby
+            .insert(client_id, PlayerInfo::new(entity, name.clone()));
+
+        server.broadcast_message(
+            ServerChannel::ServerMessages as u8,
+            error_continue!(
+                ServerMessage::SpawnPlayer {
+                    id: client_id,
+                    translation: spawn_point,
+                    weapons: Vec::new(),
+                    name
+                }
+                .bytes()
+            ),
+        )
+    }
+
     for message in sim_events.read() {
         match message {
-            SimulationEvent::PlayerPicksUpPickup { id, player, pickup } => {
+            SimulationEvent::PlayerPicksUpPickup {
+                id,
+                player,
+                classname,
+            } => {
                 let remove_message = ServerMessage::DespawnPickup { id: *id };
+                // Sent like the spawn, so it can't be lost or arrive before it
                 server.broadcast_message(
-                    ServerChannel::NetworkedEntities as u8,
+                    ServerChannel::ServerMessages as u8,
                     error_continue!(remove_message.bytes()),
                 );
-                let pickup_message = ClientMessage::PickupWeapon {
-                    weapon: pickup.clone(),
+                // What the pickup gives is always the server's own definition
+                let data = option_continue!(nw.pickup_map.0.get(classname));
+                let pickup_message = ClientMessage::Pickup {
+                    pickup_type: data.pickup_type.clone(),
+                    gives: data.gives.clone(),
                 };
 
                 update_world(*player, &pickup_message, &mut nw);
@@ -255,8 +443,8 @@ pub fn server_events(
                 };
 
                 let bytes = error_continue!(pickup_message_wrapped.bytes());
-
-                server.broadcast_message(ServerChannel::NetworkedEntities as u8, bytes);
+                // Health, armor and ammo are kept by both sides, so the grant can't be lost
+                server.broadcast_message(ServerChannel::ServerMessages as u8, bytes);
             }
         }
     }
@@ -268,35 +456,41 @@ pub fn client_events(
     mut server_events: EventWriter<ServerMessage>,
     mut connections: EventReader<Connections>,
     mut first_time: ResMut<MapFirstRun>,
+    unverified: Res<UnverifiedClients>,
 ) {
-    set_nw!(&nw, &server, &server_events);
     if first_time.0 {
         first_time.0 = false;
-        nw.plugins
-            .default
-            .map_init()
-            .expect("failed running `map_init`");
+        nw.qwak_context.start_map();
+        nw.plugins.map_init();
     }
     for message in connections.read() {
         match message {
-            Connections::Join(id) => error_continue!(nw.plugins.default.map_player_join(*id)),
+            Connections::Join(id) => nw.plugins.map_player_join(*id),
             Connections::Leave(id, reason) => {
-                error_continue!(nw.plugins.default.map_player_leave(PlayerLeave {
+                nw.plugins.map_player_leave(PlayerLeave {
                     id: *id,
-                    reason: reason.clone()
-                }));
+                    reason: reason.clone(),
+                });
             }
         }
     }
 
     for client_id in server.clients_id() {
+        // Clients which haven't been verified may only send their handshake
+        let waiting = unverified.is_waiting(client_id);
         while let Some(message) = server.receive_message(client_id, ClientChannel::Input as u8) {
             let message = error_continue!(ClientMessage::from_bytes(&message));
+            if waiting && !matches!(message, ClientMessage::Handshake(_)) {
+                continue;
+            }
             handle_client_message(&mut server, client_id, message, &mut nw, &mut server_events);
         }
 
         while let Some(message) = server.receive_message(client_id, ClientChannel::Command as u8) {
             let message = error_continue!(ClientMessage::from_bytes(&message));
+            if waiting && !matches!(message, ClientMessage::Handshake(_)) {
+                continue;
+            }
             handle_client_message(&mut server, client_id, message, &mut nw, &mut server_events);
         }
     }
@@ -322,13 +516,21 @@ pub fn handle_client_message(
             let (int, _) =
                 option_return!(player.interact(player_entity, rapier_context, cam_trans, &trans));
             let (_e, int) = option_return!(nw.interactables.get(int).ok());
-            set_nw!(nw, server, server_events);
-            error_return!(nw.plugins.default.map_interact(MapInteraction {
+            nw.plugins.map_interact(MapInteraction {
                 script: int.script.to_string(),
                 target: int.target.as_ref().map(|s| s.to_string()),
                 argument: int.argument.as_ref().map(|s| s.to_string()),
-                player_id: client_id
-            }));
+                player_id: client_id,
+            });
+        }
+        ClientMessage::Reload => {
+            // The host's own player has already reloaded
+            if client_id == nw.current_id.0 {
+                return;
+            }
+            let player = option_return!(nw.lobby.get(&client_id)).entity;
+            let (_, mut player, _) = error_return!(nw.players.get_mut(player));
+            player.remote_reload(nw.time.elapsed_secs());
         }
         ClientMessage::Fire { attack } => {
             let mut hit_pos = Vec::new();
@@ -340,6 +542,18 @@ pub fn handle_client_message(
             let cam = option_return!(player.children.camera);
             let (_, cam_trans) = error_return!(nw.cameras.get(cam));
 
+            // The host's own player has already used the ammo when shooting
+            if client_id != nw.current_id.0 {
+                if player.is_reloading(nw.time.elapsed_secs()) {
+                    warn!("player {client_id} fired while reloading");
+                    return;
+                }
+                if !player.use_ammo(attack) {
+                    warn!("player {client_id} fired without ammo");
+                    return;
+                }
+            }
+
             let (slot, row) = option_return!(player.current_weapon);
             let attack_weapon = Some(player.weapons[slot][row].data.id.clone());
             let hits = player.attack(
@@ -391,7 +605,7 @@ pub fn handle_client_message(
                         error!("weird attack 2");
                         0.0
                     };
-                    hit_player.health -= damage;
+                    hit_player.take_damage(damage);
                     if hit_player.id != nw.current_id.0 {
                         server.send_message(
                             hit_player.id,
@@ -410,11 +624,34 @@ pub fn handle_client_message(
                 error_return!(msg.bytes()),
             );
         }
+        ClientMessage::Handshake(info) => {
+            nw.commands.queue(move |world: &mut World| {
+                world.resource_scope(|world, mut server: Mut<RenetServer>| {
+                    world.resource_scope(|world, mut kicks: Mut<PendingKicks>| {
+                        let kicked = verify_client(
+                            &mut kicks,
+                            &mut server,
+                            client_id,
+                            world.resource(),
+                            &info,
+                            world.resource(),
+                        );
+                        let mut unverified = world.resource_mut::<UnverifiedClients>();
+                        if kicked {
+                            unverified.waiting.remove(&client_id);
+                        } else {
+                            unverified.verify(client_id);
+                        }
+                    });
+                });
+            });
+        }
+        ClientMessage::RequestMap => {
+            nw.commands
+                .queue(move |world: &mut World| map_transfer::queue_map(world, client_id));
+        }
         ClientMessage::RequestLobbyInfo => {
-            set_nw!(nw, server, server_events);
-            let msg = ServerMessage::LobbyInfo(
-                error_return!(nw.plugins.default.map_get_lobby_info()).into(),
-            );
+            let msg = ServerMessage::LobbyInfo(nw.plugins.map_get_lobby_info().into());
             if client_id == nw.current_id.0 {
                 server_events.send(msg);
             } else {
@@ -422,12 +659,40 @@ pub fn handle_client_message(
                 server.send_message(client_id, ServerChannel::ServerMessages as u8, bytes);
             }
         }
+        ClientMessage::Chat { text } => {
+            let name = option_return!(nw.lobby.get(&client_id)).name.clone();
+            transmit_message(server, nw, format!("{name}: {text}"));
+        }
+        ClientMessage::Command { line } => match nw.qwak_context.parse_command(client_id, &line) {
+            Ok(call) => nw.plugins.plugin_command(call),
+            Err(text) if client_id == nw.current_id.0 => display_message(nw, text),
+            Err(text) => server.send_message(
+                client_id,
+                ServerChannel::ServerMessages as u8,
+                error_return!(ServerMessage::Message { text }.bytes()),
+            ),
+        },
+        ClientMessage::MenuSelect { menu, choice } => {
+            if !nw.qwak_context.select_menu(client_id, &menu, &choice) {
+                warn!(
+                    "player {client_id} picked {choice:?} in menu {menu:?}, which it wasn't offered"
+                );
+                return;
+            }
+            nw.plugins.map_menu_select(MenuSelection {
+                player_id: client_id,
+                menu: menu.to_string(),
+                choice: choice.to_string(),
+            });
+        }
         ClientMessage::RequestRespawn => {
-            set_nw!(nw, server, server_events);
-            error_return!(nw.plugins.default.map_player_respawn(PlayerKilled {
+            nw.plugins.map_player_respawn(PlayerKilled {
                 player_id: client_id,
-                by_id: None
-            }));
+                by_id: None,
+            });
+        }
+        ClientMessage::Pickup { .. } => {
+            warn!("player {client_id} sent a pickup, which only the server can grant");
         }
         message => {
             update_world(client_id, &message, nw);
@@ -445,6 +710,93 @@ pub fn handle_client_message(
     }
 }
 
+/// Exists while the lobby has to be spawned into a new map once it is done loading.
+#[derive(Debug, Resource)]
+pub struct RespawnLobby;
+
+/// Switches the server and every connected client over to the requested map.
+pub fn change_map(
+    mut commands: Commands,
+    request: Res<ChangeMap>,
+    rotation: Res<MapRotation>,
+    mut current_map: ResMut<CurrentMap>,
+    mut server: ResMut<RenetServer>,
+) {
+    commands.remove_resource::<ChangeMap>();
+    let map = match &request.0 {
+        Some(map) => map.clone(),
+        None => {
+            let Some(map) = rotation.next(&current_map.0) else {
+                warn!("can't change to the next map, the map rotation is empty");
+                return;
+            };
+            map
+        }
+    };
+    let file = error_return!(vfs::read_to_string(&map));
+    let settings = read_map_settings(&error_return!(map_parser::parse(&file)));
+    let hash = error_return!(map_transfer::map_hash(&map));
+
+    info!("changing map to: {map:?}");
+    server.broadcast_message(
+        ServerChannel::ServerMessages as u8,
+        error_return!(
+            ServerMessage::SetMap {
+                map: map.clone(),
+                hash,
+                settings: settings.clone(),
+            }
+            .bytes()
+        ),
+    );
+    current_map.0 = map;
+    commands.insert_resource(settings);
+    commands.insert_resource(ChangingMap);
+    commands.insert_resource(RespawnLobby);
+}
+
+/// Spawns the lobby into the new map after a map change, and tells everyone about it.
+pub fn respawn_lobby(mut server: ResMut<RenetServer>, mut nw: NetWorld) {
+    nw.commands.remove_resource::<RespawnLobby>();
+    let spawn_point = nw.player_spawn.0;
+    let ids = nw.lobby.keys().copied().collect::<Vec<_>>();
+    for id in ids {
+        // The host spawns its own player
+        if id != nw.current_id.0 {
+            let entity = Player::spawn(&mut nw, false, spawn_point, id, Vec::new(), None, false);
+            option_continue!(nw.lobby.get_mut(&id)).entity = entity;
+        }
+
+        let name = option_continue!(nw.lobby.get(&id)).name.clone();
+        server.broadcast_message(
+            ServerChannel::ServerMessages as u8,
+            error_continue!(
+                ServerMessage::SpawnPlayer {
+                    id,
+                    translation: spawn_point,
+                    weapons: Vec::new(),
+                    name
+                }
+                .bytes()
+            ),
+        );
+    }
+
+    for (pickup, trans) in &nw.pickups_query {
+        server.broadcast_message(
+            ServerChannel::ServerMessages as u8,
+            error_continue!(
+                ServerMessage::SpawnPickup {
+                    id: pickup.id,
+                    translation: trans.translation,
+                    data: pickup.data.clone()
+                }
+                .bytes()
+            ),
+        )
+    }
+}
+
 pub fn init_server(
     world: &mut World,
     next_state: &mut NextState<NetState>,
@@ -483,6 +835,9 @@ pub fn init_server(
     }
     world.insert_resource(server);
     world.insert_resource(Lobby::default());
+    world.insert_resource(map_transfer::MapTransfers::default());
+    world.insert_resource(PendingKicks::default());
+    world.insert_resource(UnverifiedClients::default());
     next_state.set(NetState::Server);
     info!("started server...");
     true
@@ -493,6 +848,9 @@ fn clean_up(commands: &mut World) {
     commands.remove_non_send_resource::<SteamServerTransport>();
     commands.remove_resource::<RenetServer>();
     commands.remove_resource::<Lobby>();
+    commands.remove_resource::<map_transfer::MapTransfers>();
+    commands.remove_resource::<PendingKicks>();
+    commands.remove_resource::<UnverifiedClients>();
 }
 
 pub fn system_cleanup() -> SystemConfigs {
@@ -500,7 +858,27 @@ pub fn system_cleanup() -> SystemConfigs {
 }
 
 pub fn systems() -> SystemConfigs {
-    (server_events, client_events, frag_checker).into_configs()
+    (
+        // New players need to be known to the plugins before they are told about them
+        (
+            server_events,
+            crate::qwak_host_functions::sync_host_context,
+            client_events,
+        )
+            .chain(),
+        frag_checker,
+        map_transfer::send_map_transfers,
+        process_kicks,
+        process_handshakes,
+        report_qwak_faults,
+        QwakTicker::tick.run_if(if_map_done_loading),
+        change_map.run_if(resource_exists::<ChangeMap>),
+        respawn_lobby
+            .run_if(resource_exists::<RespawnLobby>)
+            .run_if(not(resource_exists::<ChangingMap>))
+            .run_if(if_map_done_loading),
+    )
+        .into_configs()
 }
 
 pub fn errors() -> SystemConfigs {
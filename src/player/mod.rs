@@ -1,7 +1,7 @@
 use crate::entities::message::Message;
 use bevy::prelude::*;
 use faststr::FastStr;
-use qwak_helper_types::WeaponData;
+use qwak_helper_types::{PickupType, WeaponData};
 use std::collections::HashMap;
 
 mod debug;
@@ -41,6 +41,10 @@ pub struct WeaponState {
     anim_time: f32,
     need_to_reload: bool,
     reload_timer: f32,
+    /// When the server lets the weapon fire again after a reload.
+    reloaded_at: f32,
+    /// The ammo left in the magazine.
+    pub magazine: u32,
     pub data: WeaponData,
 }
 
@@ -49,6 +53,7 @@ enum GameButtonEvents {
     Options,
     Leave,
     Respawn,
+    NextMap,
 }
 
 #[derive(Debug, Default)]
@@ -57,6 +62,7 @@ pub struct PlayerChildren {
     pub fps_model: Option<Entity>,
     pub health_hud: Option<Entity>,
     pub armour_hud: Option<Entity>,
+    pub powerup_hud: Option<Entity>,
     pub hurt_flash: Option<Entity>,
     pub ammo_hud: Option<Entity>,
     pub debug_hud: Option<Entity>,
@@ -104,6 +110,10 @@ pub struct Player {
     pub max_health: f32,
     pub armor: f32,
     pub hurt_flash: f32,
+    /// How much of each ammo type the player has.
+    pub ammo: HashMap<FastStr, u32>,
+    /// The seconds left of each powerup the player has.
+    pub powerups: HashMap<FastStr, f32>,
 
     velocity: Vec3,
     hort_speed: f32,
@@ -111,7 +121,6 @@ pub struct Player {
     jump_height: f32,
     on_ground: bool,
     head_hit: bool,
-    gravity: f32,
 
     camera_movement: CameraMovement,
 
@@ -144,13 +153,14 @@ impl Default for Player {
             dead: false,
             armor: 0.0,
             hurt_flash: 0.0,
+            ammo: Default::default(),
+            powerups: Default::default(),
             velocity: Vec3::ZERO,
             hort_friction: 8.0,
             hort_speed: 8.0,
             on_ground: false,
             head_hit: false,
             jump_height: 7.0,
-            gravity: -15.0,
             half_height: 0.5,
             radius: 0.15,
             air_time: None,
@@ -185,11 +195,18 @@ impl Default for Player {
 impl Player {
     pub fn add_weapon(&mut self, data: WeaponData, slot: usize, mesh: Handle<Scene>) -> bool {
         if !self.weapons[slot].iter().any(|c| c.data.id == data.id) {
+            let mut magazine = 0;
+            if let Some(ammo) = &data.ammo {
+                magazine = ammo.magazine;
+                *self.ammo.entry(ammo.ammo_type.clone()).or_default() += ammo.given;
+            }
             self.weapons[slot].push(WeaponState {
                 need_to_reload: false,
+                magazine,
                 data,
                 mesh,
                 reload_timer: 0.0,
+                reloaded_at: 0.0,
                 timer: 0.0,
                 anim_time: 0.0,
             });
@@ -204,6 +221,125 @@ impl Player {
         }
     }
 
+    /// Takes the ammo for an attack with the current weapon, returning false if there isn't enough.
+    pub fn use_ammo(&mut self, attack: usize) -> bool {
+        let Some((slot, row)) = self.current_weapon else {
+            return false;
+        };
+        let weapon = &mut self.weapons[slot][row];
+        let Some(ammo) = &weapon.data.ammo else {
+            return true;
+        };
+        let per_shot = if attack == 2 {
+            ammo.per_shot2
+        } else {
+            ammo.per_shot1
+        };
+        let left = if ammo.magazine == 0 {
+            self.ammo.entry(ammo.ammo_type.clone()).or_default()
+        } else {
+            &mut weapon.magazine
+        };
+        if *left < per_shot {
+            return false;
+        }
+        *left -= per_shot;
+        true
+    }
+
+    /// Fills the magazine of the current weapon from the reserve, returning false if nothing was moved.
+    pub fn reload(&mut self) -> bool {
+        let Some((slot, row)) = self.current_weapon else {
+            return false;
+        };
+        let weapon = &mut self.weapons[slot][row];
+        let Some(ammo) = &weapon.data.ammo else {
+            return false;
+        };
+        let reserve = self.ammo.entry(ammo.ammo_type.clone()).or_default();
+        let amount = ammo.magazine.saturating_sub(weapon.magazine).min(*reserve);
+        if amount == 0 {
+            return false;
+        }
+        *reserve -= amount;
+        weapon.magazine += amount;
+        true
+    }
+
+    /// Reloads the current weapon of a remote player, which can't fire again
+    /// until its reload time has passed since `now`.
+    pub fn remote_reload(&mut self, now: f32) -> bool {
+        if !self.reload() {
+            return false;
+        }
+        let Some((slot, row)) = self.current_weapon else {
+            return false;
+        };
+        let weapon = &mut self.weapons[slot][row];
+        let reload_time = weapon.data.ammo.as_ref().map_or(0.0, |a| a.reload_time);
+        weapon.reloaded_at = now + reload_time;
+        true
+    }
+
+    /// Whether the current weapon is still reloading at `now`, see [Player::remote_reload].
+    pub fn is_reloading(&self, now: f32) -> bool {
+        self.current_weapon
+            .is_some_and(|(slot, row)| now < self.weapons[slot][row].reloaded_at)
+    }
+
+    /// Hurts the player, with the armor taking part of the damage.
+    pub fn take_damage(&mut self, amount: f32) {
+        let absorbed = (amount * ARMOR_ABSORPTION).min(self.armor).max(0.0);
+        self.armor -= absorbed;
+        self.health -= amount - absorbed;
+    }
+
+    /// Whether picking up `pickup` would give the player anything.
+    pub fn can_pickup(&self, pickup: &PickupType, gives: &FastStr) -> bool {
+        if self.dead {
+            return false;
+        }
+        match pickup {
+            PickupType::Weapon => !self.weapons.iter().flatten().any(|w| &w.data.id == gives),
+            PickupType::Health { max, .. } => self.health < *max,
+            PickupType::Armor { max, .. } => self.armor < *max,
+            PickupType::Ammo { max, .. } => {
+                self.ammo.get(gives).copied().unwrap_or_default() < *max
+            }
+            PickupType::Powerup { max, .. } => {
+                self.powerups.get(gives).copied().unwrap_or_default() < *max
+            }
+        }
+    }
+
+    /// Gives the player a pickup which is not a weapon, returning the message to show.
+    pub fn add_pickup(&mut self, pickup: &PickupType, gives: &FastStr) -> String {
+        match pickup {
+            PickupType::Weapon => {
+                error!("weapon pickups are given with add_weapon");
+                String::new()
+            }
+            PickupType::Health { amount, max } => {
+                self.health = (self.health + amount).min(*max).max(self.health);
+                format!("{HEALTH_GLYPH}{amount} health")
+            }
+            PickupType::Armor { amount, max } => {
+                self.armor = (self.armor + amount).min(*max).max(self.armor);
+                format!("{ARMOR_GLYPH}{amount} armor")
+            }
+            PickupType::Ammo { amount, max } => {
+                let ammo = self.ammo.entry(gives.clone()).or_default();
+                *ammo = (*ammo + amount).min(*max).max(*ammo);
+                format!("+{amount} {gives}")
+            }
+            PickupType::Powerup { amount, max } => {
+                let left = self.powerups.entry(gives.clone()).or_default();
+                *left = (*left + amount).min(*max).max(*left);
+                format!("{gives}!")
+            }
+        }
+    }
+
     pub fn display_message(
         &self,
         commands: &mut Commands,
@@ -232,3 +368,5 @@ impl Player {
 
 const HEALTH_GLYPH: &str = "+";
 const ARMOR_GLYPH: &str = "Δ";
+/// The share of the damage taken by armor instead of health, while it lasts.
+const ARMOR_ABSORPTION: f32 = 2.0 / 3.0;
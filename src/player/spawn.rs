@@ -26,14 +26,17 @@ use bevy::{
 };
 use bevy_atmosphere::plugin::AtmosphereCamera;
 use bevy_rapier3d::prelude::*;
+use bevy_renet::renet::RenetServer;
 use bevy_scene_hook::reload::{Hook, SceneBundle as HookedSceneBundle};
 use faststr::FastStr;
 use qwak_helper_types::PlayerSpawnInfo;
 use resources::{Paused, PlayerSpawned, PlayerSpawnpoint};
 
 impl Player {
+    #[allow(clippy::too_many_arguments)]
     pub fn spawn_own_player(
         mut nw: NetWorld,
+        server: Option<Res<RenetServer>>,
         player_spawn: Res<PlayerSpawnpoint>,
         avatar: Option<Res<CurrentAvatar>>,
         steam: Option<Res<SteamClient>>,
@@ -61,15 +64,18 @@ impl Player {
             id,
             Vec::new(),
             avatar.as_ref(),
+            server.is_some(),
         );
 
-        nw.lobby.insert(
-            nw.current_id.0,
-            PlayerInfo::new(
-                entity,
-                FastStr::from(steam.map(|s| s.friends().name()).unwrap_or(format!("{id}"))),
-            ),
-        );
+        nw.lobby
+            .entry(id)
+            .and_modify(|info| info.entity = entity)
+            .or_insert_with(|| {
+                PlayerInfo::new(
+                    entity,
+                    FastStr::from(steam.map(|s| s.friends().name()).unwrap_or(format!("{id}"))),
+                )
+            });
 
         player_spawned.0 = true
     }
@@ -81,11 +87,13 @@ impl Player {
         current_id: u64,
         weapons: Vec<Vec<FastStr>>,
         avatar: Option<&Res<CurrentAvatar>>,
+        is_host: bool,
     ) -> Entity {
         let mut camera = None;
         let mut fps_model = None;
         let mut ammo_hud = None;
         let mut armour_hud = None;
+        let mut powerup_hud = None;
         let mut health_hud = None;
         let mut debug_hud = None;
         let mut message_holder = None;
@@ -96,7 +104,7 @@ impl Player {
         let mut pause_screen = None;
         let mut death_splash = None;
 
-        let info = nw.plugins.default.player_info().unwrap_or_default();
+        let info = nw.plugins.player_info();
         println!("{info:?}");
 
         let player_commands = entity
@@ -359,6 +367,27 @@ impl Player {
                         );
                     });
 
+                    c.spawn(Node {
+                        position_type: PositionType::Absolute,
+                        right: Val::Px(10.0),
+                        top: Val::Px(10.0),
+                        ..default()
+                    })
+                    .with_children(|c| {
+                        powerup_hud = Some(
+                            c.spawn((
+                                Text::default(),
+                                TextFont {
+                                    font: nw.asset_server.load("ui/Color Basic.otf"),
+                                    ..default()
+                                },
+                                TextColor(text_color),
+                                TextLayout::new_with_justify(JustifyText::Right),
+                            ))
+                            .id(),
+                        );
+                    });
+
                     c.spawn(Node {
                         position_type: PositionType::Absolute,
                         left: Val::Px(255.0),
@@ -503,6 +532,15 @@ impl Player {
                                 None,
                                 GameButtonEvents::Options,
                             ));
+                            if is_host {
+                                c.spawn(MenuButton::new(
+                                    "Next map",
+                                    None,
+                                    None,
+                                    None,
+                                    GameButtonEvents::NextMap,
+                                ));
+                            }
                             c.spawn(MenuButton::new(
                                 "Leave",
                                 None,
@@ -525,6 +563,7 @@ impl Player {
                 fps_model,
                 ammo_hud,
                 armour_hud,
+                powerup_hud,
                 health_hud,
                 debug_hud,
                 message_holder,
@@ -6,9 +6,8 @@ use super::{
 };
 use crate::{
     entities::ProjectileEntity,
-    net::{ClientChannel, ClientMessage, NetState, ServerMessage, server::NW_PTR},
+    net::{ClientChannel, ClientMessage, NetState},
     queries::NetWorld,
-    set_nw,
 };
 use bevy::{
     audio::Volume,
@@ -29,9 +28,10 @@ use faststr::FastStr;
 use macros::{error_continue, option_continue, option_return};
 use qwak_helper_types::{Attack, PlayerKilled, SoundEffect};
 use resources::{
-    CurrentStage, Paused, Projectiles,
+    ChangeMap, CurrentStage, Paused, Projectiles,
     entropy::{EGame, EMisc, Entropy},
     inputs::PlayerInput,
+    map_settings::MapSettings,
 };
 use std::mem::transmute;
 
@@ -61,6 +61,7 @@ impl Player {
             Player::weapon_animations,
             Player::camera_movement,
             Player::update_hud,
+            Player::update_powerups,
             Player::hurt_flash,
             Player::pause_screen_buttons,
         )
@@ -79,7 +80,7 @@ impl Player {
 
     #[allow(clippy::type_complexity, clippy::too_many_arguments)]
     fn pause_screen_buttons(
-        nw: NetWorld,
+        mut nw: NetWorld,
         interactions: Query<
             (&Interaction, &GameButtonEvents),
             (Changed<Interaction>, With<Button>),
@@ -89,7 +90,6 @@ impl Player {
         mut client: Option<ResMut<RenetClient>>,
         mut current_stage: ResMut<NextState<CurrentStage>>,
         mut net_state: ResMut<NextState<NetState>>,
-        server_events: EventWriter<ServerMessage>,
     ) {
         for (interaction, event) in &interactions {
             if matches!(interaction, Interaction::Pressed) {
@@ -109,13 +109,16 @@ impl Player {
                         net_state.set(NetState::Offline);
                         current_stage.set(CurrentStage::MainMenu);
                     }
+                    GameButtonEvents::NextMap => {
+                        paused.0 = false;
+                        nw.commands.insert_resource(ChangeMap(None));
+                    }
                     GameButtonEvents::Respawn => {
-                        if let Some(server) = &mut server {
-                            set_nw!(&nw, &server, &server_events);
-                            error_continue!(nw.plugins.default.map_player_respawn(PlayerKilled {
+                        if server.is_some() {
+                            nw.plugins.map_player_respawn(PlayerKilled {
                                 player_id: nw.current_id.0,
-                                by_id: None
-                            }));
+                                by_id: None,
+                            });
                         }
                         if let Some(client) = &mut client {
                             client.send_message(
@@ -162,7 +165,27 @@ impl Player {
     ) {
         for player in &q_players {
             let ammo_hud = option_continue!(player.children.ammo_hud);
-            let _ammo_hud = error_continue!(text.get_mut(ammo_hud));
+            let (mut ammo_hud, mut ammo_vis) = error_continue!(text.get_mut(ammo_hud));
+            let weapon = player
+                .current_weapon
+                .and_then(|(slot, row)| player.weapons.get(slot)?.get(row));
+            if let Some((weapon, ammo)) = weapon.and_then(|w| Some((w, w.data.ammo.as_ref()?))) {
+                let reserve = player
+                    .ammo
+                    .get(&ammo.ammo_type)
+                    .copied()
+                    .unwrap_or_default();
+                let name = ammo.ammo_type.to_uppercase();
+                ammo_hud.0 = if ammo.magazine > 0 {
+                    format!("{}/{reserve}\n{name}", weapon.magazine)
+                } else {
+                    format!("{reserve}\n{name}")
+                };
+                ammo_vis.set_if_neq(Visibility::Inherited);
+            } else {
+                // Weapons without ammo never run out
+                ammo_vis.set_if_neq(Visibility::Hidden);
+            }
 
             let health_hud = option_continue!(player.children.health_hud);
             let (mut health_hud, _) = error_continue!(text.get_mut(health_hud));
@@ -172,6 +195,16 @@ impl Player {
             let (mut armour_hud, _) = error_continue!(text.get_mut(armour_hud));
             armour_hud.0 = format!("{ARMOR_GLYPH}{}", player.armor.round());
 
+            let powerup_hud = option_continue!(player.children.powerup_hud);
+            let (mut powerup_hud, _) = error_continue!(text.get_mut(powerup_hud));
+            let mut powerups = player.powerups.iter().collect::<Vec<_>>();
+            powerups.sort_by_key(|(name, _)| *name);
+            powerup_hud.0 = powerups
+                .into_iter()
+                .map(|(name, left)| format!("{name} {}", left.ceil()))
+                .collect::<Vec<_>>()
+                .join("\n");
+
             let lobby_hud = option_continue!(player.children.lobby_hud);
             let (mut text, mut vis) = error_continue!(text.get_mut(lobby_hud));
             if input.show_lobby_just_pressed {
@@ -186,6 +219,19 @@ impl Player {
         }
     }
 
+    /// Counts down the powerups of every player, removing them once they run out.
+    pub fn update_powerups(mut players: Query<&mut Player>, time: Res<Time>) {
+        for mut player in &mut players {
+            if player.powerups.is_empty() {
+                continue;
+            }
+            player.powerups.retain(|_, left| {
+                *left -= time.delta_secs();
+                *left > 0.0
+            });
+        }
+    }
+
     pub fn shoot(
         mut commands: Commands,
         mut q_players: Query<(Entity, &mut Player, &Transform), With<PlayerController>>,
@@ -222,16 +268,21 @@ impl Player {
                 return;
             }
             weapon.need_to_reload = false;
+            let anim_time = weapon.anim_time;
+
+            if player.start_reload(keys.weapon_reload_just_pressed, &time, &mut client_events) {
+                return;
+            }
 
             let mut shot = false;
 
-            if keys.weapon_shoot2_pressed && !weapon.need_to_reload {
+            if keys.weapon_shoot2_pressed && player.use_ammo(2) {
                 player.attack2(&time, &mut client_events);
                 shot = true;
-            } else if keys.weapon_shoot1_pressed && !weapon.need_to_reload {
+            } else if keys.weapon_shoot1_pressed && player.use_ammo(1) {
                 player.attack1(&time, &mut client_events);
                 shot = true;
-            } else if weapon.anim_time <= 0.0 && player.current_weapon_anim != "idle" {
+            } else if anim_time <= 0.0 && player.current_weapon_anim != "idle" {
                 player.current_weapon_anim = FastStr::from("idle");
                 player.restart_anim = true;
             }
@@ -365,6 +416,7 @@ impl Player {
         cameras: Query<(&Camera3d, &Transform), Without<PlayerController>>,
         mut events: EventWriter<ClientMessage>,
         paused: Res<Paused>,
+        map_settings: Res<MapSettings>,
     ) {
         for (mut controller, mut player, gt) in &mut query {
             // movement
@@ -435,9 +487,9 @@ impl Player {
             if keys.jump_just_pressed && player.on_ground && !paused.0 && !player.dead {
                 player.velocity.y = player.jump_height;
             } else if player.velocity.y > 0.0 && player.head_hit && !player.on_ground {
-                player.velocity.y += player.gravity * time.delta_secs() * 4.0;
+                player.velocity.y += map_settings.gravity * time.delta_secs() * 4.0;
             } else if !player.on_ground {
-                player.velocity.y += player.gravity * time.delta_secs();
+                player.velocity.y += map_settings.gravity * time.delta_secs();
             } else {
                 player.velocity.y = 0.0;
             }
@@ -996,34 +1048,7 @@ impl Player {
             }
             Attack::Projectile { projectile } => {
                 if let Some(proj) = projectile_map.0.get(projectile) {
-                    // Fix mesh rotation
-                    let mut trans = Transform::from_translation(origin);
-                    trans.scale = Vec3::splat(proj.scale);
-                    trans.rotation = player_trans.rotation;
-
-                    let look_dir = origin + dir;
-
-                    trans.rotation = player_trans.rotation;
-                    trans.look_at(look_dir, Vec3::Y);
-                    trans.rotate_x(proj.rotation[0].to_radians());
-                    trans.rotate_y(proj.rotation[1].to_radians());
-                    trans.rotate_z(proj.rotation[2].to_radians());
-
-                    commands
-                        .spawn((
-                            Mesh3d(asset_server.load(&*proj.model_file)),
-                            MeshMaterial3d(materials.add(StandardMaterial {
-                                base_color_texture: Some(asset_server.load(&*proj.texture_file)),
-                                perceptual_roughness: 1.0,
-                                reflectance: 0.0,
-                                ..default()
-                            })),
-                            trans,
-                        ))
-                        .insert(ProjectileEntity {
-                            data: proj.clone(),
-                            dir,
-                        });
+                    ProjectileEntity::spawn(commands, asset_server, materials, proj, origin, dir);
                 } else {
                     error!("Unknown projectile: {projectile}")
                 }
@@ -1036,6 +1061,37 @@ impl Player {
         }
     }
 
+    /// Starts reloading the current weapon if the player asked to,
+    /// or if it reloads by itself and can't fire another shot.
+    fn start_reload(
+        &mut self,
+        asked: bool,
+        time: &Time,
+        client_events: &mut EventWriter<ClientMessage>,
+    ) -> bool {
+        let (slot, row) = option_return!(self.current_weapon);
+        let weapon = &self.weapons[slot][row];
+        let ammo = option_return!(&weapon.data.ammo);
+        let empty = weapon.magazine < ammo.per_shot1.min(ammo.per_shot2);
+        let reload_time = ammo.reload_time;
+        if !(asked || (ammo.auto_reload && empty)) || !self.reload() {
+            return false;
+        }
+
+        let weapon = &mut self.weapons[slot][row];
+        weapon.timer = reload_time + time.delta_secs();
+        weapon.anim_time = reload_time + time.delta_secs();
+        weapon.reload_timer = 0.0;
+        self.current_weapon_anim = match weapon.data.animations.reload {
+            Some(_) => FastStr::from("reload"),
+            None => FastStr::from("idle"),
+        };
+        self.restart_anim = true;
+
+        client_events.send(ClientMessage::Reload);
+        true
+    }
+
     fn attack1(&mut self, time: &Time, client_events: &mut EventWriter<ClientMessage>) {
         let (slot, row) = option_return!(self.current_weapon);
         let weapon = &mut self.weapons[slot][row];
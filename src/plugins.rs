@@ -1,47 +1,42 @@
 use crate::{
     entities::{ProjectileEntity, message::Message, pickup::PickupEntity},
     mainmenu,
-    map_gen::{clean_up_map, load_map, texture_systems::*, world_entites},
-    net::{self, NetState},
+    map_gen::{
+        MapBuildTasks, MapParseTask, change_map, clean_up_map,
+        hot_reload::{MapReloading, reload_map},
+        load_map,
+        map_settings::apply_map_settings,
+        poll_map_build, read_map,
+        texture_systems::*,
+        world_entites,
+    },
+    net::{self, AssetManifest, ConnectInfo, NetState, map_transfer::MapDownload},
     player::Player,
-    qwak_host_functions::qwak_functions,
+    qwak_host_functions::{
+        QwakContext, apply_host_commands, qwak_functions, save_storage, sync_host_context,
+    },
+    qwak_modules::{LaunchArgs, QwakModules},
+    qwaks::{QwakTicker, Qwaks},
     startup,
+    ui::{chat, hud, loading_screen, plugin_menu},
 };
 use bevy::prelude::*;
-use qwak::*;
 use resources::{
     entropy::{entropy_game, entropy_misc},
     inputs::PlayerInput,
+    map_settings::MapSettings,
     *,
 };
 use std::{collections::HashMap, path::PathBuf};
 
-#[derive(Debug, Resource)]
-pub struct Qwaks {
-    pub default: QwakPlugin,
-}
-impl Qwaks {
-    fn new(functions: impl IntoIterator<Item = qwak::Function>) -> Self {
-        info!("Loading qwaks...");
-        let default = match QwakPlugin::new("assets/qwaks/default.wasm", functions) {
-            Ok(o) => o,
-            Err(e) => panic!("failed loading default qwak: {e}"),
-        };
-        default.plugin_init().unwrap();
-
-        info!("Done loading qwaks...");
-        Self { default }
-    }
-}
-
 pub struct Resources;
 impl Resources {
-    fn get_map() -> PathBuf {
-        if let Some(map) = std::env::args().nth(1) {
-            if std::fs::File::open(&map).is_ok() {
-                return map.into();
+    fn get_map(args: &LaunchArgs) -> PathBuf {
+        if let Some(map) = &args.map {
+            if vfs::get().exists(map) {
+                return map.clone();
             } else {
-                error!("Can't find map: \"{map}\"")
+                error!("Can't find map: {map:?}")
             }
         }
 
@@ -50,25 +45,43 @@ impl Resources {
 }
 impl Plugin for Resources {
     fn build(&self, app: &mut App) {
-        let qwaks = Qwaks::new(qwak_functions());
+        let context = QwakContext::default();
+        context.set_admins(&app.world().resource::<LaunchArgs>().admins);
+        context.load_storage(&app.world().resource::<QwakModules>().active.name);
+        let qwaks = Qwaks::new(
+            || qwak_functions(&context),
+            &app.world().resource::<LaunchArgs>().mutators,
+            app.world().resource::<LaunchArgs>().disable_faulty_qwaks,
+        );
+        let manifest = AssetManifest::load();
+        let map = Self::get_map(app.world().resource::<LaunchArgs>());
+        let tick_rate = app.world().resource::<LaunchArgs>().tick_rate;
+        let connect_info =
+            ConnectInfo::new(&qwaks, app.world().resource::<QwakModules>(), &manifest);
         app.init_state::<CurrentStage>()
             .init_state::<NetState>()
-            .insert_resource(CurrentMap(Self::get_map()))
-            .insert_resource(TextureLoadingState::NotLoaded)
+            .insert_resource(CurrentMap(map))
+            .insert_resource(MapRotation::load())
+            .insert_resource(MapLoadingState::NotLoaded)
+            .insert_resource(MapLoadingProgress::default())
+            .insert_resource(MapSettings::default())
             .insert_resource(PlayerSpawned(false))
             .insert_resource(TexturesLoading::default())
             .insert_resource(TextureMap::default())
             .insert_resource(PlayerSpawnpoint(Vec3::ZERO))
-            .insert_resource(MapDoneLoading(false))
             .insert_resource(Paused(false))
             .insert_resource(MapFirstRun(true))
-            .insert_resource(PickupMap(qwaks.default.plugin_get_pickups().unwrap()))
-            .insert_resource(WeaponMap(qwaks.default.plugin_get_weapons().unwrap()))
+            .insert_resource(PickupMap(qwaks.plugin_get_pickups()))
+            .insert_resource(WeaponMap(qwaks.plugin_get_weapons()))
             .insert_resource(PlayerInput::default())
             .insert_resource(entropy_game())
             .insert_resource(entropy_misc())
-            .insert_resource(Projectiles(qwaks.default.plugin_get_projectiles().unwrap()))
+            .insert_resource(Projectiles(qwaks.plugin_get_projectiles()))
             .insert_resource(TargetMap(HashMap::default()))
+            .insert_resource(connect_info)
+            .insert_resource(manifest)
+            .insert_resource(context)
+            .insert_resource(QwakTicker::new(tick_rate))
             .insert_resource(qwaks);
     }
 }
@@ -100,6 +113,7 @@ impl Plugin for ClientPlugin {
                 net::client::errors(),
                 net::client::errors_steam(),
                 net::client::all_cons(),
+                net::map_transfer::receive_map_transfer.run_if(resource_exists::<MapDownload>),
             )
                 .run_if(in_state(NetState::Client)),
         )
@@ -130,7 +144,9 @@ impl Plugin for MainMenuStage {
     fn build(&self, app: &mut App) {
         app.add_systems(
             OnEnter(CurrentStage::MainMenu),
-            mainmenu::setup.run_if(in_state(CurrentStage::MainMenu)),
+            (mainmenu::setup, mainmenu::connect_on_launch)
+                .chain()
+                .run_if(in_state(CurrentStage::MainMenu)),
         )
         .add_systems(
             Update,
@@ -152,23 +168,75 @@ impl Plugin for MainMenuStage {
 pub struct GameStage;
 impl Plugin for GameStage {
     fn build(&self, app: &mut bevy::prelude::App) {
-        app.add_systems(OnEnter(CurrentStage::InGame), register_textures)
+        app.add_systems(OnExit(CurrentStage::InGame), clean_up_map)
+            .add_systems(
+                Update,
+                register_textures
+                    .run_if(in_state(CurrentStage::InGame))
+                    .run_if(if_map_not_loaded)
+                    .run_if(not(resource_exists::<MapDownload>)),
+            )
+            .add_systems(
+                Update,
+                (loading_screen::spawn, loading_screen::update)
+                    .chain()
+                    .run_if(in_state(CurrentStage::InGame))
+                    .run_if(not(if_map_done_loading)),
+            )
+            .add_systems(
+                Update,
+                loading_screen::despawn
+                    .run_if(in_state(CurrentStage::InGame))
+                    .run_if(if_map_done_loading),
+            )
+            .add_systems(OnExit(CurrentStage::InGame), loading_screen::despawn)
             .add_systems(
                 Update,
                 world_entites::systems().run_if(in_state(CurrentStage::InGame)),
             )
+            .add_systems(
+                Update,
+                change_map
+                    .run_if(in_state(CurrentStage::InGame))
+                    .run_if(resource_exists::<ChangingMap>),
+            )
+            .add_systems(
+                Update,
+                reload_map
+                    .run_if(in_state(CurrentStage::InGame))
+                    .run_if(resource_exists_and_changed::<MapReloading>),
+            )
             .add_systems(
                 Update,
                 texture_waiter
                     .run_if(in_state(CurrentStage::InGame))
                     .run_if(if_texture_loading),
             )
+            .add_systems(
+                Update,
+                read_map
+                    .run_if(in_state(CurrentStage::InGame))
+                    .run_if(if_building_geometry)
+                    .run_if(not(resource_exists::<MapParseTask>))
+                    .run_if(not(resource_exists::<MapBuildTasks>)),
+            )
             .add_systems(
                 Update,
                 load_map
                     .run_if(in_state(CurrentStage::InGame))
-                    .run_if(if_texture_done_loading)
-                    .run_if(not(if_map_done_loading)),
+                    .run_if(resource_exists::<MapParseTask>),
+            )
+            .add_systems(
+                Update,
+                poll_map_build
+                    .run_if(in_state(CurrentStage::InGame))
+                    .run_if(resource_exists::<MapBuildTasks>),
+            )
+            .add_systems(
+                Update,
+                apply_map_settings
+                    .run_if(in_state(CurrentStage::InGame))
+                    .run_if(if_map_done_loading),
             )
             .add_systems(
                 Update,
@@ -181,6 +249,15 @@ impl Plugin for GameStage {
                 PreUpdate,
                 PlayerInput::update.run_if(in_state(CurrentStage::InGame)),
             )
+            .add_systems(
+                First,
+                sync_host_context.run_if(in_state(CurrentStage::InGame)),
+            )
+            .add_systems(
+                PostUpdate,
+                apply_host_commands.run_if(in_state(CurrentStage::InGame)),
+            )
+            .add_systems(Last, save_storage)
             .add_systems(
                 Update,
                 (
@@ -188,14 +265,46 @@ impl Plugin for GameStage {
                     PickupEntity::systems(),
                     ProjectileEntity::systems(),
                     Message::update_messages,
+                    hud::update_timers,
+                    plugin_menu::buttons,
+                    plugin_menu::grab_cursor.after(Player::pause_handler),
+                    (chat::open, chat::update, chat::submit).after(Player::pause_handler),
                 )
                     .run_if(in_state(CurrentStage::InGame)), //.run_if(if_not_paused),
             )
-            .add_systems(Update, Player::input_systems().run_if(if_not_paused))
             .add_systems(
                 Update,
-                (Player::pause_handler, Player::debug).run_if(in_state(CurrentStage::InGame)),
+                Player::input_systems()
+                    .run_if(if_not_paused)
+                    .run_if(chat::not_typing)
+                    .run_if(plugin_menu::no_menu_open),
             )
-            .add_systems(OnExit(CurrentStage::InGame), clean_up_map);
+            .add_systems(
+                Update,
+                (
+                    Player::pause_handler.run_if(chat::not_typing),
+                    Player::debug,
+                )
+                    .run_if(in_state(CurrentStage::InGame)),
+            );
+
+        // Developer mode: rebuild the map when it is saved in the editor
+        #[cfg(not(feature = "production"))]
+        app.init_resource::<crate::map_gen::hot_reload::MapWatcher>()
+            .add_systems(
+                Update,
+                crate::map_gen::hot_reload::MapWatcher::watch
+                    .run_if(in_state(CurrentStage::InGame))
+                    .run_if(in_state(NetState::Server))
+                    .run_if(if_map_done_loading),
+            );
+
+        // Developer mode: reload the qwaks when they are rebuilt
+        #[cfg(not(feature = "production"))]
+        app.init_resource::<crate::qwaks::QwakWatcher>()
+            .add_systems(
+                Update,
+                crate::qwaks::QwakWatcher::watch.run_if(in_state(NetState::Server)),
+            );
     }
 }
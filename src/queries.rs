@@ -4,12 +4,13 @@ use crate::{
     net::{CurrentClientId, Lobby},
     particles::ParticleMap,
     player::Player,
-    plugins::Qwaks,
+    qwak_host_functions::QwakContext,
+    qwaks::Qwaks,
 };
 use bevy::{ecs::system::SystemParam, prelude::*};
 use bevy_rapier3d::plugin::RapierContext;
 use resources::{
-    PlayerSpawnpoint, Projectiles, TargetMap, WeaponMap,
+    PickupMap, PlayerSpawnpoint, Projectiles, TargetMap, WeaponMap,
     entropy::{EGame, Entropy},
 };
 
@@ -37,6 +38,7 @@ pub struct NetWorld<'w, 's> {
     pub rapier_context: Query<'w, 's, &'static RapierContext>,
     pub asset_server: Res<'w, AssetServer>,
     pub weapon_map: Res<'w, WeaponMap>,
+    pub pickup_map: Res<'w, PickupMap>,
     pub materials: ResMut<'w, Assets<StandardMaterial>>,
     pub meshes: ResMut<'w, Assets<Mesh>>,
     pub game_entropy: ResMut<'w, Entropy<EGame>>,
@@ -47,5 +49,6 @@ pub struct NetWorld<'w, 's> {
     pub lobby: ResMut<'w, Lobby>,
     pub particles: Res<'w, ParticleMap>,
     pub plugins: Res<'w, Qwaks>,
+    pub qwak_context: Res<'w, QwakContext>,
     pub targets: Res<'w, TargetMap>,
 }
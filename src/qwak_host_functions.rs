@@ -1,173 +1,987 @@
-#![allow(static_mut_refs)]
 use crate::{
-    get_nw,
+    entities::PluginEntity,
+    map_gen::entities::spawn_pickup,
     net::{
-        ServerChannel, ServerMessage,
-        server::{NW_PTR, transmit_message},
+        CurrentClientId, Lobby, ServerChannel, ServerMessage,
+        server::{display_message, transmit_message},
     },
+    player::Player,
+    queries::NetWorld,
+    qwak_storage::QwakStorage,
+};
+use bevy::{math::Vec3, prelude::*};
+use bevy_renet::renet::RenetServer;
+use faststr::FastStr;
+use macros::{error_continue, option_continue};
+use qwak::UserData;
+use qwak_helper_types::{
+    CommandArgKind, CommandCall, CommandSignature, CommandValue, HostError, HudWidget,
+    MapInteraction, Menu, MsgVec3, PlayerState, StorageScope, TargetEntity,
+};
+use resources::{ChangeMap, CurrentMap, PickupMap, PlayerSpawnpoint, Projectiles, TargetMap};
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
 };
-use bevy::math::Vec3;
-pub use inner::functions as qwak_functions;
-use macros::{error_continue, error_return};
-use qwak_helper_types::{MapInteraction, MsgVec3};
-use qwak_shared::QwakHostFunctions;
 
-qwak_shared::host_gen!(Host);
-struct Host;
-impl QwakHostFunctions for Host {
-    fn game__player__get_name(id: u64) -> String {
-        let (nw, _, _) = get_nw!();
-        let name = nw
-            .lobby
-            .get(&id)
-            .map(|pi| pi.name.to_string())
-            .unwrap_or_else(|| "unknown player".to_string());
-        name
-    }
-
-    fn log__error(message: String) {
-        bevy::log::error!(target: "plugin", "{message}");
+qwak_shared::host_gen!(Host, HostContext);
+
+/// The host functions, sharing the [HostContext] in `context`.
+pub fn qwak_functions(context: &QwakContext) -> impl IntoIterator<Item = qwak::Function> {
+    inner::functions(&context.0)
+}
+
+/// A request a plugin has made, applied to the world by [apply_host_commands].
+#[derive(Debug)]
+pub enum HostCommand {
+    /// Shows a message to every player.
+    Message(String),
+    /// Sent to every player, including the host.
+    Broadcast(ServerMessage),
+    /// Sent to every player like [HostCommand::Broadcast], but never lost or reordered.
+    /// Used for state which clients keep, like the entities which exist.
+    BroadcastReliable(ServerMessage),
+    /// Sent to a single player, which may be the host.
+    SendTo {
+        id: u64,
+        msg: ServerMessage,
+    },
+    Hurt {
+        id: u64,
+        damage: f32,
+    },
+    Heal {
+        id: u64,
+        amount: f32,
+    },
+    Teleport {
+        id: u64,
+        location: Vec3,
+    },
+    ChangeMap(Option<PathBuf>),
+    SpawnPickup {
+        id: u64,
+        pickup: FastStr,
+        translation: Vec3,
+    },
+}
+
+/// The first id of entities spawned by plugins, the ids below it belong to map entities.
+const PLUGIN_ENTITY_START: u64 = 1 << 32;
+
+/// What the host functions know about the game, and the requests they have made.
+#[derive(Debug, Default)]
+pub struct HostContext {
+    /// The players in the lobby.
+    players: HashMap<u64, PlayerState>,
+    /// The entities in the map by target name.
+    targets: HashMap<String, Vec<TargetEntity>>,
+    spawn_point: Vec3,
+    host_id: u64,
+    map: String,
+    /// When the current map was loaded, in seconds since the game started.
+    map_start: f32,
+    time: f32,
+    /// The ids of the pickups and projectiles plugins have defined.
+    pickups: HashSet<String>,
+    projectiles: HashSet<String>,
+    /// The entities spawned by plugins which still exist.
+    entities: HashSet<u64>,
+    /// The ids handed out for entities which haven't been spawned yet.
+    pending: HashSet<u64>,
+    /// How many entities plugins have spawned.
+    spawned: u64,
+    /// The commands plugins have registered by name.
+    chat_commands: HashMap<String, CommandSignature>,
+    /// The players who can run admin commands, besides the host.
+    admins: HashSet<u64>,
+    /// The menus each player has open by name, with the ids of their choices.
+    menus: HashMap<u64, HashMap<String, HashSet<String>>>,
+    storage: QwakStorage,
+    commands: Vec<HostCommand>,
+}
+
+impl HostContext {
+    fn check_player(&self, id: u64) -> Result<&PlayerState, HostError> {
+        self.players.get(&id).ok_or(HostError::UnknownPlayer(id))
     }
 
-    #[allow(clippy::print_stderr)]
-    fn log__debug(value: String) {
-        bevy::log::debug!(target: "plugin", "{value}");
+    fn check_target(&self, target: &str) -> Result<&Vec<TargetEntity>, HostError> {
+        self.targets
+            .get(target)
+            .ok_or_else(|| HostError::UnknownTarget(target.to_string()))
     }
 
-    fn game__broadcast_message(value: String) {
-        let (nw, server, _) = get_nw!();
-        transmit_message(server, nw, value);
+    /// Sends `msg` to `player`, or everyone if it is `None`, over the reliable channel.
+    fn send(&mut self, player: Option<u64>, msg: ServerMessage) -> Result<(), HostError> {
+        let command = match player {
+            Some(id) => {
+                self.check_player(id)?;
+                HostCommand::SendTo { id, msg }
+            }
+            None => HostCommand::BroadcastReliable(msg),
+        };
+        self.commands.push(command);
+        Ok(())
     }
 
-    fn game__brush__translate(target_name: String, x: f32, y: f32, z: f32, delay: u32) {
-        let (_, server, sw) = get_nw!();
-        let target_name = target_name.into();
+    fn is_admin(&self, id: u64) -> bool {
+        id == self.host_id || self.admins.contains(&id)
+    }
 
-        let translate = ServerMessage::TranslateBrush {
-            target: target_name,
-            translation: Vec3::new(x, y, z),
-            delay,
+    /// Finds a player by id or by name, ignoring case.
+    fn find_player(&self, player: &str) -> Option<u64> {
+        if let Ok(id) = player.parse()
+            && self.players.contains_key(&id)
+        {
+            return Some(id);
+        }
+        self.players
+            .values()
+            .find(|p| p.name.eq_ignore_ascii_case(player))
+            .map(|p| p.id)
+    }
+
+    /// Parses a command line such as `givegun weapon_rpg`, without the `/`.
+    fn parse_command(&self, player_id: u64, line: &str) -> Result<CommandCall, String> {
+        let mut words = line.split_whitespace();
+        let name = words.next().ok_or("no command given")?;
+        let command = self
+            .chat_commands
+            .get(name)
+            .ok_or_else(|| format!("unknown command /{name}"))?;
+        if command.admin && !self.is_admin(player_id) {
+            return Err(format!("/{name} can only be used by admins"));
+        }
+        let usage = || {
+            let mut usage = format!("usage: /{name}");
+            for arg in &command.args {
+                match arg.optional {
+                    true => usage += &format!(" [{}]", arg.name),
+                    false => usage += &format!(" <{}>", arg.name),
+                }
+            }
+            usage
         };
-        let bytes = error_return!(translate.bytes());
-        sw.send(translate);
-        server.broadcast_message(ServerChannel::NetworkedEntities as u8, bytes);
+
+        let mut args = Vec::new();
+        for arg in &command.args {
+            let Some(word) = words.next() else {
+                if arg.optional {
+                    break;
+                }
+                return Err(format!("missing {}, {}", arg.name, usage()));
+            };
+            let value = match arg.kind {
+                CommandArgKind::String => Some(CommandValue::String(word.to_string())),
+                CommandArgKind::Int => word.parse().ok().map(CommandValue::Int),
+                CommandArgKind::Float => word.parse().ok().map(CommandValue::Float),
+                CommandArgKind::Bool => word.parse().ok().map(CommandValue::Bool),
+                CommandArgKind::Player => self.find_player(word).map(CommandValue::Player),
+            };
+            args.push(value.ok_or_else(|| format!("invalid {} {word:?}, {}", arg.name, usage()))?);
+        }
+        if words.next().is_some() {
+            return Err(format!("too many arguments, {}", usage()));
+        }
+        Ok(CommandCall {
+            player_id,
+            command: name.to_string(),
+            args,
+        })
     }
 
-    fn game__brush__rotate(target_name: String, x: f32, y: f32, z: f32, delay: u32) {
-        let (_, server, sw) = get_nw!();
-        let target_name = target_name.into();
+    /// Remembers the menu `player` (or everyone if `None`) has been shown,
+    /// `choices` is `None` if it was closed.
+    fn set_menu(&mut self, player: Option<u64>, name: &str, choices: Option<HashSet<String>>) {
+        let players = match player {
+            Some(id) => vec![id],
+            None => self.players.keys().copied().collect(),
+        };
+        for id in players {
+            let menus = self.menus.entry(id).or_default();
+            match &choices {
+                Some(choices) => menus.insert(name.to_string(), choices.clone()),
+                None => menus.remove(name),
+            };
+        }
+    }
 
-        let translate = ServerMessage::RotateBrush {
-            target: target_name,
-            translation: Vec3::new(x, y, z),
-            delay,
+    /// Returns true if `player` has been shown `menu` with `choice` in it,
+    /// which is closed as the player picked something.
+    fn select_menu(&mut self, player: u64, menu: &str, choice: &str) -> bool {
+        let Some(menus) = self.menus.get_mut(&player) else {
+            return false;
         };
-        let bytes = error_return!(translate.bytes());
-        sw.send(translate);
-        server.broadcast_message(ServerChannel::NetworkedEntities as u8, bytes);
+        if !menus
+            .get(menu)
+            .is_some_and(|choices| choices.contains(choice))
+        {
+            return false;
+        }
+        menus.remove(menu);
+        true
+    }
+
+    /// Returns an id for a new entity, which can't be the id of a map entity.
+    fn spawn_id(&mut self) -> u64 {
+        let id = PLUGIN_ENTITY_START + self.spawned;
+        self.spawned += 1;
+        self.entities.insert(id);
+        self.pending.insert(id);
+        id
     }
+}
+
+fn vec3(v: MsgVec3) -> Vec3 {
+    Vec3::new(v.x, v.y, v.z)
+}
 
-    fn game__audio__global__play(path: String, volume: f32) {
-        let (_, server, sw) = get_nw!();
-        let translate = ServerMessage::PlaySoundGlobally {
-            sound: path.into(),
-            volume,
+fn msg_vec3(v: Vec3) -> MsgVec3 {
+    MsgVec3 {
+        x: v.x,
+        y: v.y,
+        z: v.z,
+    }
+}
+
+/// The [HostContext] shared by every plugin.
+#[derive(Resource, Clone)]
+pub struct QwakContext(UserData<HostContext>);
+impl Default for QwakContext {
+    fn default() -> Self {
+        Self(UserData::new(HostContext::default()))
+    }
+}
+impl QwakContext {
+    fn with<R>(&self, f: impl FnOnce(&mut HostContext) -> R) -> Option<R> {
+        let context = self.0.get().ok()?;
+        let mut context = match context.lock() {
+            Ok(o) => o,
+            Err(e) => e.into_inner(),
         };
-        let bytes = error_return!(translate.bytes());
-        sw.send(translate);
-        server.broadcast_message(ServerChannel::NetworkedEntities as u8, bytes);
-    }
-
-    fn game__player__hurt(id: u64, damage: f32) {
-        let (nw, server, sw) = get_nw!();
-        for (_, mut hit_player, _) in &mut nw.players {
-            if hit_player.id == id {
-                let msg = ServerMessage::Hit { amount: damage };
-                if hit_player.id != nw.current_id.0 {
-                    hit_player.last_hurter = id;
-                    hit_player.health -= damage;
-                    server.send_message(
-                        hit_player.id,
+        Some(f(&mut context))
+    }
+
+    /// Lets the players with these ids run admin commands.
+    pub fn set_admins(&self, admins: &[u64]) {
+        self.with(|context| context.admins = admins.iter().copied().collect());
+    }
+
+    /// Loads the persistent storage of a game module.
+    pub fn load_storage(&self, module: &str) {
+        self.with(|context| context.storage = QwakStorage::load(module));
+    }
+
+    /// Returns true if `player` has been shown `menu` with `choice` in it, closing it.
+    pub fn select_menu(&self, player: u64, menu: &str, choice: &str) -> bool {
+        self.with(|context| context.select_menu(player, menu, choice))
+            .unwrap_or_default()
+    }
+
+    /// Forgets what was only kept for the previous map, and saves the persistent storage.
+    pub fn start_map(&self) {
+        self.with(|context| {
+            context.menus.clear();
+            context.entities.clear();
+            context.pending.clear();
+            context.storage.clear_map();
+            context.storage.save();
+        });
+    }
+
+    /// Checks a command a player ran against the command plugins have registered,
+    /// returning the reason if it can't be run.
+    pub fn parse_command(&self, player_id: u64, line: &str) -> Result<CommandCall, String> {
+        self.with(|context| context.parse_command(player_id, line))
+            .unwrap_or_else(|| Err("commands are unavailable".to_string()))
+    }
+}
+
+/// Updates what the host functions know about the game.
+#[allow(clippy::too_many_arguments)]
+pub fn sync_host_context(
+    context: Res<QwakContext>,
+    lobby: Option<Res<Lobby>>,
+    current_id: Option<Res<CurrentClientId>>,
+    spawn_point: Res<PlayerSpawnpoint>,
+    targets: Res<TargetMap>,
+    current_map: Res<CurrentMap>,
+    time: Res<Time>,
+    players: Query<(&Player, &Transform)>,
+    transforms: Query<&Transform>,
+    pickups: Res<PickupMap>,
+    projectiles: Res<Projectiles>,
+    entities: Query<&PluginEntity>,
+) {
+    context.with(|context| {
+        if pickups.is_changed() {
+            context.pickups = pickups.0.keys().map(|k| k.to_string()).collect();
+        }
+        if projectiles.is_changed() {
+            context.projectiles = projectiles.0.keys().map(|k| k.to_string()).collect();
+        }
+        context.entities = entities.iter().map(|e| e.0).collect();
+        let existing = &context.entities;
+        context.pending.retain(|id| !existing.contains(id));
+        context.entities.extend(context.pending.iter().copied());
+        context.players = lobby
+            .map(|lobby| {
+                lobby
+                    .iter()
+                    .map(|(id, info)| {
+                        let player = players.get(info.entity).ok();
+                        let state = PlayerState {
+                            id: *id,
+                            name: info.name.to_string(),
+                            position: msg_vec3(
+                                player.map(|(_, t)| t.translation).unwrap_or_default(),
+                            ),
+                            health: player.map(|(p, _)| p.health).unwrap_or_default(),
+                            armor: player.map(|(p, _)| p.armor).unwrap_or_default(),
+                            alive: player.is_some_and(|(p, _)| !p.dead),
+                            weapon: player.and_then(|(p, _)| {
+                                let (slot, row) = p.current_weapon?;
+                                Some(p.weapons.get(slot)?.get(row)?.data.id.to_string())
+                            }),
+                            ammo: player
+                                .map(|(p, _)| {
+                                    p.ammo.iter().map(|(k, v)| (k.to_string(), *v)).collect()
+                                })
+                                .unwrap_or_default(),
+                            powerups: player
+                                .map(|(p, _)| {
+                                    p.powerups
+                                        .iter()
+                                        .map(|(k, v)| (k.to_string(), *v))
+                                        .collect()
+                                })
+                                .unwrap_or_default(),
+                        };
+                        (*id, state)
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let players = &context.players;
+        context.menus.retain(|id, _| players.contains_key(id));
+        context.targets = targets
+            .iter()
+            .map(|(name, entities)| {
+                let entities = transforms
+                    .iter_many(entities)
+                    .map(|t| TargetEntity {
+                        position: msg_vec3(t.translation),
+                        rotation: t.rotation.to_array(),
+                        scale: msg_vec3(t.scale),
+                    })
+                    .collect();
+                (name.to_string(), entities)
+            })
+            .collect();
+        if current_map.is_changed() {
+            context.map = current_map.0.to_string_lossy().to_string();
+            context.map_start = time.elapsed_secs();
+        }
+        context.time = time.elapsed_secs();
+        context.host_id = current_id.map(|id| id.0).unwrap_or_default();
+        context.spawn_point = spawn_point.0;
+    });
+}
+
+/// How often the persistent values plugins have stored are written to disk, in seconds.
+const STORAGE_SAVE_INTERVAL: f32 = 10.0;
+
+/// Saves the persistent storage now and then, and when the game exits.
+pub fn save_storage(
+    context: Res<QwakContext>,
+    time: Res<Time<Real>>,
+    mut exit: EventReader<AppExit>,
+    mut last_save: Local<f32>,
+) {
+    let now = time.elapsed_secs();
+    if exit.read().next().is_none() && now - *last_save < STORAGE_SAVE_INTERVAL {
+        return;
+    }
+    *last_save = now;
+    context.with(|context| context.storage.save());
+}
+
+/// Applies the requests plugins have made since the last time.
+/// Without a server only the local player is affected.
+pub fn apply_host_commands(
+    context: Res<QwakContext>,
+    mut server: Option<ResMut<RenetServer>>,
+    mut nw: NetWorld,
+    mut sw: EventWriter<ServerMessage>,
+    pickups: Res<PickupMap>,
+) {
+    let commands = context
+        .with(|context| std::mem::take(&mut context.commands))
+        .unwrap_or_default();
+    for command in commands {
+        match command {
+            HostCommand::Message(text) => match &mut server {
+                Some(server) => transmit_message(server, &mut nw, text),
+                None => display_message(&mut nw, text),
+            },
+            HostCommand::Broadcast(msg) => {
+                if let Some(server) = &mut server {
+                    server.broadcast_message(
                         ServerChannel::NetworkedEntities as u8,
                         error_continue!(msg.bytes()),
-                    )
-                } else {
-                    sw.send(ServerMessage::Hit { amount: damage });
+                    );
                 }
+                sw.send(msg);
             }
-        }
-    }
-
-    fn game__player__heal(id: u64, heal: f32) {
-        let (nw, server, sw) = get_nw!();
-        for (_, mut hit_player, _) in &mut nw.players {
-            if hit_player.id == id {
-                let msg = ServerMessage::Heal { amount: heal };
-                if hit_player.id != nw.current_id.0 {
-                    hit_player.last_hurter = id;
-                    hit_player.health += heal;
-                    hit_player.health = hit_player.health.min(hit_player.max_health);
+            HostCommand::BroadcastReliable(msg) => {
+                if let Some(server) = &mut server {
+                    server.broadcast_message(
+                        ServerChannel::ServerMessages as u8,
+                        error_continue!(msg.bytes()),
+                    );
+                }
+                sw.send(msg);
+            }
+            HostCommand::SendTo { id, msg } => {
+                if id == nw.current_id.0 {
+                    sw.send(msg);
+                } else if let Some(server) = &mut server {
+                    server.send_message(
+                        id,
+                        ServerChannel::ServerMessages as u8,
+                        error_continue!(msg.bytes()),
+                    );
+                }
+            }
+            HostCommand::Hurt { id, damage } => {
+                for (_, mut hit_player, _) in &mut nw.players {
+                    if hit_player.id != id {
+                        continue;
+                    }
+                    let msg = ServerMessage::Hit { amount: damage };
+                    if hit_player.id == nw.current_id.0 {
+                        sw.send(msg);
+                    } else if let Some(server) = &mut server {
+                        hit_player.last_hurter = id;
+                        hit_player.take_damage(damage);
+                        server.send_message(
+                            hit_player.id,
+                            ServerChannel::NetworkedEntities as u8,
+                            error_continue!(msg.bytes()),
+                        )
+                    }
+                }
+            }
+            HostCommand::Heal { id, amount } => {
+                for (_, mut hit_player, _) in &mut nw.players {
+                    if hit_player.id != id {
+                        continue;
+                    }
+                    let msg = ServerMessage::Heal { amount };
+                    if hit_player.id == nw.current_id.0 {
+                        sw.send(msg);
+                    } else if let Some(server) = &mut server {
+                        hit_player.last_hurter = id;
+                        hit_player.health += amount;
+                        hit_player.health = hit_player.health.min(hit_player.max_health);
+                        server.send_message(
+                            hit_player.id,
+                            ServerChannel::NetworkedEntities as u8,
+                            error_continue!(msg.bytes()),
+                        )
+                    }
+                }
+            }
+            HostCommand::Teleport { id, location } => {
+                let msg = ServerMessage::TeleportPlayer { location };
+                if id == nw.current_id.0 {
+                    sw.send(msg);
+                } else if let Some(server) = &mut server {
                     server.send_message(
-                        hit_player.id,
+                        id,
                         ServerChannel::NetworkedEntities as u8,
                         error_continue!(msg.bytes()),
-                    )
+                    );
+                }
+            }
+            HostCommand::ChangeMap(map) => {
+                if server.is_some() {
+                    nw.commands.insert_resource(ChangeMap(map));
                 } else {
-                    sw.send(ServerMessage::Heal { amount: heal });
+                    error!("only the host can change map");
+                }
+            }
+            HostCommand::SpawnPickup {
+                id,
+                pickup,
+                translation,
+            } => {
+                let data = option_continue!(pickups.0.get(&pickup));
+                let entity = spawn_pickup(
+                    id,
+                    true,
+                    translation,
+                    &nw.asset_server,
+                    data,
+                    &mut nw.commands,
+                    &mut nw.materials,
+                );
+                nw.commands.entity(entity).insert(PluginEntity(id));
+                // The host has its own pickup, the others get one without a collider
+                if let Some(server) = &mut server {
+                    let msg = ServerMessage::SpawnPickup {
+                        id,
+                        translation,
+                        data: data.clone(),
+                    };
+                    server.broadcast_message(
+                        ServerChannel::ServerMessages as u8,
+                        error_continue!(msg.bytes()),
+                    );
                 }
             }
         }
     }
+}
 
-    fn game__map__timeout(map_interaction: MapInteraction, delay: u32) {
-        let (_, server, sw) = get_nw!();
-        let translate = ServerMessage::CreateTimer {
-            delay,
-            map_interaction,
-        };
-        let bytes = error_return!(translate.bytes());
-        sw.send(translate);
-        server.broadcast_message(ServerChannel::NetworkedEntities as u8, bytes);
+struct Host;
+#[allow(non_snake_case)]
+impl Host {
+    fn game__player__message(
+        context: &mut HostContext,
+        id: u64,
+        text: String,
+    ) -> Result<(), HostError> {
+        context.send(Some(id), ServerMessage::Message { text })
+    }
+
+    fn game__player__get_name(context: &mut HostContext, id: u64) -> Result<String, HostError> {
+        Ok(context.check_player(id)?.name.clone())
+    }
+
+    fn log__error(_: &mut HostContext, message: String) {
+        bevy::log::error!(target: "plugin", "{message}");
+    }
+
+    fn log__debug(_: &mut HostContext, value: String) {
+        bevy::log::debug!(target: "plugin", "{value}");
+    }
+
+    fn game__broadcast_message(context: &mut HostContext, value: String) {
+        context.commands.push(HostCommand::Message(value));
+    }
+
+    fn game__brush__translate(
+        context: &mut HostContext,
+        target_name: String,
+        x: f32,
+        y: f32,
+        z: f32,
+        delay: u32,
+    ) -> Result<(), HostError> {
+        context.check_target(&target_name)?;
+        context
+            .commands
+            .push(HostCommand::Broadcast(ServerMessage::TranslateBrush {
+                target: target_name.into(),
+                translation: Vec3::new(x, y, z),
+                delay,
+            }));
+        Ok(())
+    }
+
+    fn game__brush__rotate(
+        context: &mut HostContext,
+        target_name: String,
+        x: f32,
+        y: f32,
+        z: f32,
+        delay: u32,
+    ) -> Result<(), HostError> {
+        context.check_target(&target_name)?;
+        context
+            .commands
+            .push(HostCommand::Broadcast(ServerMessage::RotateBrush {
+                target: target_name.into(),
+                translation: Vec3::new(x, y, z),
+                delay,
+            }));
+        Ok(())
+    }
+
+    fn game__audio__global__play(context: &mut HostContext, path: String, volume: f32) {
+        context
+            .commands
+            .push(HostCommand::Broadcast(ServerMessage::PlaySoundGlobally {
+                sound: path.into(),
+                volume,
+            }));
+    }
+
+    fn game__player__hurt(
+        context: &mut HostContext,
+        id: u64,
+        damage: f32,
+    ) -> Result<(), HostError> {
+        context.check_player(id)?;
+        context.commands.push(HostCommand::Hurt { id, damage });
+        Ok(())
+    }
+
+    fn game__player__heal(context: &mut HostContext, id: u64, heal: f32) -> Result<(), HostError> {
+        context.check_player(id)?;
+        context
+            .commands
+            .push(HostCommand::Heal { id, amount: heal });
+        Ok(())
     }
 
-    fn game__player__teleport(id: u64, x: f32, y: f32, z: f32) {
-        let (nw, server, sw) = get_nw!();
-        let msg = ServerMessage::TeleportPlayer {
+    fn game__map__timeout(context: &mut HostContext, map_interaction: MapInteraction, delay: u32) {
+        context
+            .commands
+            .push(HostCommand::Broadcast(ServerMessage::CreateTimer {
+                delay,
+                map_interaction,
+            }));
+    }
+
+    fn game__player__teleport(
+        context: &mut HostContext,
+        id: u64,
+        x: f32,
+        y: f32,
+        z: f32,
+    ) -> Result<(), HostError> {
+        context.check_player(id)?;
+        context.commands.push(HostCommand::Teleport {
+            id,
             location: Vec3::new(x, y, z),
-        };
-        if id == nw.current_id.0 {
-            sw.send(msg);
-        } else {
-            server.send_message(
+        });
+        Ok(())
+    }
+
+    fn game__map__spawn_point(context: &mut HostContext) -> MsgVec3 {
+        msg_vec3(context.spawn_point)
+    }
+
+    fn game__map__change(context: &mut HostContext, path: String) -> Result<(), HostError> {
+        if !path.is_empty() && !vfs::get().exists(&path) {
+            return Err(HostError::UnknownMap(path));
+        }
+        let map = (!path.is_empty()).then(|| PathBuf::from(path));
+        context.commands.push(HostCommand::ChangeMap(map));
+        Ok(())
+    }
+
+    fn game__host_id(context: &mut HostContext) -> u64 {
+        context.host_id
+    }
+
+    fn game__player__set_stats(
+        context: &mut HostContext,
+        id: u64,
+        health: f32,
+        armor: f32,
+    ) -> Result<(), HostError> {
+        context.check_player(id)?;
+        context
+            .commands
+            .push(HostCommand::Broadcast(ServerMessage::SetPlayerHealth {
                 id,
-                ServerChannel::NetworkedEntities as u8,
-                error_return!(msg.bytes()),
-            );
+                armor,
+                health,
+            }));
+        Ok(())
+    }
+
+    fn game__player__list(context: &mut HostContext) -> Vec<u64> {
+        context.players.keys().copied().collect()
+    }
+
+    fn game__player__info(context: &mut HostContext, id: u64) -> Result<PlayerState, HostError> {
+        context.check_player(id).cloned()
+    }
+
+    fn game__map__targets(
+        context: &mut HostContext,
+        target: String,
+    ) -> Result<Vec<TargetEntity>, HostError> {
+        context.check_target(&target).cloned()
+    }
+
+    fn game__map__name(context: &mut HostContext) -> String {
+        context.map.clone()
+    }
+
+    fn game__map__time(context: &mut HostContext) -> f32 {
+        context.time - context.map_start
+    }
+
+    fn game__entity__spawn_pickup(
+        context: &mut HostContext,
+        pickup: String,
+        position: MsgVec3,
+    ) -> Result<u64, HostError> {
+        if !context.pickups.contains(&pickup) {
+            return Err(HostError::UnknownDefinition(pickup));
         }
+        let id = context.spawn_id();
+        context.commands.push(HostCommand::SpawnPickup {
+            id,
+            pickup: pickup.into(),
+            translation: vec3(position),
+        });
+        Ok(id)
     }
 
-    fn game__map__spawn_point() -> MsgVec3 {
-        let (nw, _, _) = get_nw!();
-        let v = nw.player_spawn.0;
-        MsgVec3 {
-            x: v.x,
-            y: v.y,
-            z: v.z,
+    fn game__entity__spawn_projectile(
+        context: &mut HostContext,
+        projectile: String,
+        position: MsgVec3,
+        dir: MsgVec3,
+    ) -> Result<u64, HostError> {
+        if !context.projectiles.contains(&projectile) {
+            return Err(HostError::UnknownDefinition(projectile));
         }
+        let id = context.spawn_id();
+        context.commands.push(HostCommand::BroadcastReliable(
+            ServerMessage::SpawnProjectile {
+                id,
+                translation: vec3(position),
+                dir: vec3(dir).normalize_or_zero(),
+                projectile: projectile.into(),
+            },
+        ));
+        Ok(id)
+    }
+
+    fn game__entity__spawn_prop(
+        context: &mut HostContext,
+        model: String,
+        position: MsgVec3,
+        scale: f32,
+    ) -> u64 {
+        let id = context.spawn_id();
+        context
+            .commands
+            .push(HostCommand::BroadcastReliable(ServerMessage::SpawnProp {
+                id,
+                translation: vec3(position),
+                scale,
+                model: model.into(),
+            }));
+        id
+    }
+
+    fn game__entity__despawn(context: &mut HostContext, id: u64) -> Result<(), HostError> {
+        context.pending.remove(&id);
+        if !context.entities.remove(&id) {
+            return Err(HostError::UnknownEntity(id));
+        }
+        context.commands.push(HostCommand::BroadcastReliable(
+            ServerMessage::DespawnEntity { id },
+        ));
+        Ok(())
+    }
+
+    fn game__hud__set(
+        context: &mut HostContext,
+        player: Option<u64>,
+        name: String,
+        widget: HudWidget,
+    ) -> Result<(), HostError> {
+        let msg = ServerMessage::SetHud {
+            name: name.into(),
+            widget: Some(widget),
+            global: player.is_none(),
+        };
+        context.send(player, msg)
+    }
+
+    fn game__hud__remove(
+        context: &mut HostContext,
+        player: Option<u64>,
+        name: String,
+    ) -> Result<(), HostError> {
+        let msg = ServerMessage::SetHud {
+            name: name.into(),
+            widget: None,
+            global: player.is_none(),
+        };
+        context.send(player, msg)
+    }
+
+    fn game__menu__open(
+        context: &mut HostContext,
+        player: Option<u64>,
+        name: String,
+        menu: Menu,
+    ) -> Result<(), HostError> {
+        let choices = menu.choices.iter().map(|c| c.id.clone()).collect();
+        let msg = ServerMessage::OpenMenu {
+            name: name.clone().into(),
+            menu: Some(menu),
+        };
+        context.send(player, msg)?;
+        context.set_menu(player, &name, Some(choices));
+        Ok(())
+    }
+
+    fn game__menu__close(
+        context: &mut HostContext,
+        player: Option<u64>,
+        name: String,
+    ) -> Result<(), HostError> {
+        let msg = ServerMessage::OpenMenu {
+            name: name.clone().into(),
+            menu: None,
+        };
+        context.send(player, msg)?;
+        context.set_menu(player, &name, None);
+        Ok(())
+    }
+
+    fn game__command__register(context: &mut HostContext, command: CommandSignature) {
+        context.chat_commands.insert(command.name.clone(), command);
+    }
+
+    fn game__storage__get(
+        context: &mut HostContext,
+        scope: StorageScope,
+        key: String,
+    ) -> Option<Vec<u8>> {
+        context.storage.get(scope, &key).cloned()
     }
 
-    fn game__host_id() -> u64 {
-        let (nw, _, _) = get_nw!();
-        nw.current_id.0
+    fn game__storage__set(
+        context: &mut HostContext,
+        scope: StorageScope,
+        key: String,
+        value: Option<Vec<u8>>,
+    ) {
+        context.storage.set(scope, key, value);
+    }
+
+    fn game__storage__compare_and_swap(
+        context: &mut HostContext,
+        scope: StorageScope,
+        key: String,
+        expected: Option<Vec<u8>>,
+        value: Option<Vec<u8>>,
+    ) -> bool {
+        context
+            .storage
+            .compare_and_swap(scope, key, expected, value)
+    }
+
+    fn game__storage__keys(
+        context: &mut HostContext,
+        scope: StorageScope,
+        prefix: String,
+    ) -> Vec<String> {
+        context.storage.keys(scope, &prefix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use qwak_helper_types::CommandArg;
+
+    fn arg(name: &str, kind: CommandArgKind, optional: bool) -> CommandArg {
+        CommandArg {
+            name: name.to_string(),
+            kind,
+            optional,
+        }
     }
 
-    fn game__player__set_stats(id: u64, health: f32, armor: f32) {
-        let (_, server, sw) = get_nw!();
-        let msg = ServerMessage::SetPlayerHealth { id, armor, health };
-        sw.send(msg.clone());
-        server.broadcast_message(
-            ServerChannel::NetworkedEntities as u8,
-            error_return!(msg.bytes()),
+    fn context() -> HostContext {
+        let mut context = HostContext {
+            host_id: 1,
+            ..Default::default()
+        };
+        context.players.insert(
+            2,
+            PlayerState {
+                id: 2,
+                name: "Loafey".to_string(),
+                position: msg_vec3(Vec3::ZERO),
+                health: 100.0,
+                armor: 0.0,
+                alive: true,
+                weapon: None,
+                ammo: HashMap::new(),
+                powerups: HashMap::new(),
+            },
         );
+        let commands = [
+            CommandSignature {
+                name: "give".to_string(),
+                description: String::new(),
+                args: vec![
+                    arg("player", CommandArgKind::Player, false),
+                    arg("amount", CommandArgKind::Int, false),
+                    arg("scale", CommandArgKind::Float, true),
+                ],
+                admin: false,
+            },
+            CommandSignature {
+                name: "kick".to_string(),
+                description: String::new(),
+                args: vec![arg("player", CommandArgKind::Player, false)],
+                admin: true,
+            },
+        ];
+        for command in commands {
+            context.chat_commands.insert(command.name.clone(), command);
+        }
+        context
+    }
+
+    #[test]
+    fn parse_arguments() {
+        let context = context();
+        let call = context.parse_command(2, "give loafey 5 0.5").unwrap();
+        assert_eq!(call.player_id, 2);
+        assert_eq!(call.command, "give");
+        assert!(matches!(
+            call.args[..],
+            [
+                CommandValue::Player(2),
+                CommandValue::Int(5),
+                CommandValue::Float(0.5)
+            ]
+        ));
+
+        let call = context.parse_command(2, "give 2 5").unwrap();
+        assert!(matches!(
+            call.args[..],
+            [CommandValue::Player(2), CommandValue::Int(5)]
+        ));
+
+        assert!(context.parse_command(2, "give loafey").is_err());
+        assert!(context.parse_command(2, "give loafey five").is_err());
+        assert!(context.parse_command(2, "give someone 5").is_err());
+        assert!(context.parse_command(2, "unknown").is_err());
+        assert!(context.parse_command(2, "").is_err());
+    }
+
+    #[test]
+    fn too_many_arguments() {
+        let err = context()
+            .parse_command(2, "give loafey 5 0.5 1")
+            .unwrap_err();
+        assert!(err.starts_with("too many arguments"));
+    }
+
+    #[test]
+    fn admin_commands() {
+        let mut context = context();
+        assert!(context.parse_command(1, "kick loafey").is_ok());
+        assert!(context.parse_command(2, "kick loafey").is_err());
+        context.admins.insert(2);
+        assert!(context.parse_command(2, "kick loafey").is_ok());
     }
 }
@@ -0,0 +1,161 @@
+use bevy::prelude::*;
+use std::path::{Path, PathBuf};
+
+/// Where the installed qwak modules are, each in `qwaks/<name>/assets`.
+const QWAK_DIR: &str = "qwaks";
+/// The plugin inside of a module's asset directory.
+pub const QWAK_FILE: &str = "qwaks/default.wasm";
+
+/// The command line arguments.
+#[derive(Debug, Resource, Clone, Default)]
+pub struct LaunchArgs {
+    /// The map to host, the first argument which is not a flag.
+    pub map: Option<PathBuf>,
+    /// The qwak module to run, `--qwak <name>`.
+    pub qwak: Option<String>,
+    /// Mutators loaded after the module's plugin, `--mutator <name>` for each.
+    pub mutators: Vec<String>,
+    /// Disables a qwak after this many failed calls, `--disable-faulty-qwaks <count>`.
+    pub disable_faulty_qwaks: Option<u32>,
+    /// How many times per second `map_tick` is called, `--tick-rate <hz>`.
+    pub tick_rate: Option<f32>,
+    /// Players who can run admin commands besides the host, `--admin <id>` for each.
+    pub admins: Vec<u64>,
+    /// A server to join once started, `--connect <address>`.
+    pub connect: Option<String>,
+    /// Lists the installed qwak modules and exits, `--list-qwaks`.
+    pub list_qwaks: bool,
+}
+impl LaunchArgs {
+    pub fn parse() -> Self {
+        let mut res = Self::default();
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--qwak" => res.qwak = args.next(),
+                "--connect" => res.connect = args.next(),
+                "--mutator" => res.mutators.extend(args.next()),
+                "--disable-faulty-qwaks" => {
+                    res.disable_faulty_qwaks = args.next().and_then(|c| c.parse().ok())
+                }
+                "--admin" => res.admins.extend(args.next().and_then(|a| a.parse().ok())),
+                "--tick-rate" => res.tick_rate = args.next().and_then(|r| r.parse().ok()),
+                "--list-qwaks" => res.list_qwaks = true,
+                _ if arg.starts_with("--") => warn!("unknown argument: {arg}"),
+                _ => res.map = Some(arg.into()),
+            }
+        }
+        res
+    }
+
+    /// The arguments which launch the game the same way again, apart from `--list-qwaks`.
+    pub fn to_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(qwak) = &self.qwak {
+            args.extend(["--qwak".to_string(), qwak.clone()]);
+        }
+        if let Some(address) = &self.connect {
+            args.extend(["--connect".to_string(), address.clone()]);
+        }
+        for mutator in &self.mutators {
+            args.extend(["--mutator".to_string(), mutator.clone()]);
+        }
+        if let Some(count) = self.disable_faulty_qwaks {
+            args.extend(["--disable-faulty-qwaks".to_string(), count.to_string()]);
+        }
+        for admin in &self.admins {
+            args.extend(["--admin".to_string(), admin.to_string()]);
+        }
+        if let Some(rate) = self.tick_rate {
+            args.extend(["--tick-rate".to_string(), rate.to_string()]);
+        }
+        if let Some(map) = &self.map {
+            args.push(map.to_string_lossy().into_owned());
+        }
+        args
+    }
+}
+
+/// A game module, a qwak plugin together with its assets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QwakModule {
+    pub name: String,
+    pub asset_dir: PathBuf,
+}
+
+/// The installed qwak modules, and the one which is running.
+#[derive(Debug, Resource, Clone)]
+pub struct QwakModules {
+    pub active: QwakModule,
+    pub installed: Vec<QwakModule>,
+}
+impl QwakModules {
+    /// Finds the installed modules, and picks the requested one.
+    /// Without a request the `assets` directory is used if it exists, and otherwise `default`.
+    pub fn discover(requested: Option<&str>) -> Self {
+        let mut installed = std::fs::read_dir(QWAK_DIR)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|e| QwakModule {
+                name: e.file_name().to_string_lossy().into_owned(),
+                asset_dir: e.path().join("assets"),
+            })
+            .filter(|m| m.asset_dir.join(QWAK_FILE).exists())
+            .collect::<Vec<_>>();
+        installed.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let active = match requested {
+            Some(name) => match installed.iter().find(|m| m.name == name) {
+                Some(module) => module.clone(),
+                None => {
+                    error!("qwak module \"{name}\" is not installed");
+                    Self::fallback(&installed)
+                }
+            },
+            None => Self::fallback(&installed),
+        };
+        info!("running qwak module \"{}\"", active.name);
+        Self { active, installed }
+    }
+
+    fn fallback(installed: &[QwakModule]) -> QwakModule {
+        // `run.sh` used to link the module being developed to `assets`
+        let assets = Path::new(vfs::ASSET_DIR);
+        if assets.join(QWAK_FILE).exists() {
+            let canonical = assets.canonicalize().ok();
+            return installed
+                .iter()
+                .find(|m| m.asset_dir.canonicalize().ok() == canonical)
+                .cloned()
+                .unwrap_or(QwakModule {
+                    name: vfs::ASSET_DIR.to_string(),
+                    asset_dir: assets.to_path_buf(),
+                });
+        }
+        installed
+            .iter()
+            .find(|m| m.name == "default")
+            .or(installed.first())
+            .cloned()
+            .unwrap_or(QwakModule {
+                name: vfs::ASSET_DIR.to_string(),
+                asset_dir: assets.to_path_buf(),
+            })
+    }
+
+    pub fn is_installed(&self, name: &str) -> bool {
+        self.installed.iter().any(|m| m.name == name)
+    }
+
+    /// Starts the game again with `args`, usually running another module or joining a server.
+    /// The caller is expected to exit the current game afterwards.
+    pub fn relaunch(&self, args: &LaunchArgs) -> std::io::Result<()> {
+        let name = args.qwak.as_deref().unwrap_or(&self.active.name);
+        info!("restarting with qwak module \"{name}\"");
+        std::process::Command::new(std::env::current_exe()?)
+            .args(args.to_args())
+            .spawn()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,176 @@
+use bevy::log::error;
+use macros::error_return;
+use qwak_helper_types::StorageScope;
+use std::{collections::HashMap, path::PathBuf};
+
+/// Where the persistent values are saved, one file per game module.
+const SAVE_DIR: &str = "saves";
+
+type Values = HashMap<String, Vec<u8>>;
+
+/// The msgpack values plugins have stored, shared by every plugin.
+#[derive(Debug, Default)]
+pub struct QwakStorage {
+    map: Values,
+    session: Values,
+    persistent: Values,
+    /// Where the persistent values are saved, they are only kept in memory without it.
+    path: Option<PathBuf>,
+    /// Whether the persistent values have changed since they were saved.
+    dirty: bool,
+}
+impl QwakStorage {
+    /// Loads the persistent values of a game module.
+    pub fn load(module: &str) -> Self {
+        Self::load_from(PathBuf::from(SAVE_DIR).join(format!("{module}.storage")))
+    }
+
+    fn load_from(path: PathBuf) -> Self {
+        // Nothing has been saved yet if the file is missing
+        let persistent = std::fs::read(&path)
+            .ok()
+            .and_then(|bytes| match bincode::deserialize(&bytes) {
+                Ok(values) => Some(values),
+                Err(e) => {
+                    error!("failed reading {path:?}: {e}");
+                    None
+                }
+            })
+            .unwrap_or_default();
+        Self {
+            persistent,
+            path: Some(path),
+            ..Default::default()
+        }
+    }
+
+    fn values(&self, scope: StorageScope) -> &Values {
+        match scope {
+            StorageScope::Map => &self.map,
+            StorageScope::Session => &self.session,
+            StorageScope::Persistent => &self.persistent,
+        }
+    }
+
+    fn values_mut(&mut self, scope: StorageScope) -> &mut Values {
+        match scope {
+            StorageScope::Map => &mut self.map,
+            StorageScope::Session => &mut self.session,
+            StorageScope::Persistent => {
+                self.dirty = true;
+                &mut self.persistent
+            }
+        }
+    }
+
+    pub fn get(&self, scope: StorageScope, key: &str) -> Option<&Vec<u8>> {
+        self.values(scope).get(key)
+    }
+
+    /// Stores `value` under `key`, or removes it if `value` is `None`.
+    pub fn set(&mut self, scope: StorageScope, key: String, value: Option<Vec<u8>>) {
+        let values = self.values_mut(scope);
+        match value {
+            Some(value) => values.insert(key, value),
+            None => values.remove(&key),
+        };
+    }
+
+    /// Stores `value` only if the current value is `expected`, returning whether it was stored.
+    pub fn compare_and_swap(
+        &mut self,
+        scope: StorageScope,
+        key: String,
+        expected: Option<Vec<u8>>,
+        value: Option<Vec<u8>>,
+    ) -> bool {
+        if self.get(scope, &key) != expected.as_ref() {
+            return false;
+        }
+        self.set(scope, key, value);
+        true
+    }
+
+    /// The keys starting with `prefix`, sorted.
+    pub fn keys(&self, scope: StorageScope, prefix: &str) -> Vec<String> {
+        let mut keys = self
+            .values(scope)
+            .keys()
+            .filter(|k| k.starts_with(prefix))
+            .cloned()
+            .collect::<Vec<_>>();
+        keys.sort();
+        keys
+    }
+
+    /// Removes the values which are only kept for the current map.
+    pub fn clear_map(&mut self) {
+        self.map.clear();
+    }
+
+    /// Writes the persistent values to disk, if they have changed.
+    pub fn save(&mut self) {
+        if !std::mem::take(&mut self.dirty) {
+            return;
+        }
+        let Some(path) = &self.path else {
+            return;
+        };
+        let bytes = error_return!(bincode::serialize(&self.persistent));
+        if let Some(parent) = path.parent() {
+            error_return!(std::fs::create_dir_all(parent));
+        }
+        error_return!(std::fs::write(path, bytes));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compare_and_swap() {
+        let mut storage = QwakStorage::default();
+        let key = || "score".to_string();
+        assert!(!storage.compare_and_swap(StorageScope::Map, key(), Some(vec![1]), Some(vec![2])));
+        assert!(storage.compare_and_swap(StorageScope::Map, key(), None, Some(vec![1])));
+        assert!(!storage.compare_and_swap(StorageScope::Map, key(), None, Some(vec![2])));
+        assert!(storage.compare_and_swap(StorageScope::Map, key(), Some(vec![1]), None));
+        assert_eq!(storage.get(StorageScope::Map, "score"), None);
+    }
+
+    #[test]
+    fn keys_by_prefix() {
+        let mut storage = QwakStorage::default();
+        for key in ["kills.b", "kills.a", "deaths.a"] {
+            storage.set(StorageScope::Session, key.to_string(), Some(vec![0]));
+        }
+        assert_eq!(
+            storage.keys(StorageScope::Session, "kills."),
+            ["kills.a", "kills.b"]
+        );
+        assert_eq!(storage.keys(StorageScope::Session, "").len(), 3);
+        assert!(storage.keys(StorageScope::Map, "").is_empty());
+    }
+
+    #[test]
+    fn save_and_load() {
+        let dir = std::env::temp_dir().join(format!("qwak_storage_test_{}", std::process::id()));
+        let path = dir.join("test.storage");
+
+        let mut storage = QwakStorage::load_from(path.clone());
+        storage.set(StorageScope::Persistent, "best".to_string(), Some(vec![7]));
+        storage.set(StorageScope::Session, "current".to_string(), Some(vec![3]));
+        storage.save();
+
+        let mut storage = QwakStorage::load_from(path);
+        assert_eq!(
+            storage.get(StorageScope::Persistent, "best"),
+            Some(&vec![7])
+        );
+        assert_eq!(storage.get(StorageScope::Session, "current"), None);
+        storage.clear_map();
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
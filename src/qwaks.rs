@@ -0,0 +1,382 @@
+use crate::qwak_modules::QWAK_FILE;
+use bevy::prelude::*;
+use faststr::FastStr;
+use qwak::{QwakLimits, QwakPlugin};
+use qwak_helper_types::{
+    CommandCall, MapInteraction, MenuSelection, PlayerKilled, PlayerLeave, PlayerSpawnInfo,
+    QwakManifest,
+};
+use std::{
+    collections::HashMap,
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, AtomicU32, Ordering},
+    },
+};
+
+/// Where mutators are stored, as `qwaks/mutators/<name>.wasm`.
+const MUTATOR_DIR: &str = "qwaks/mutators";
+
+/// A loaded qwak plugin.
+#[derive(Debug)]
+pub struct LoadedQwak {
+    /// `default` for the game module's plugin, otherwise the name of the mutator.
+    pub name: String,
+    /// The asset path the plugin was loaded from.
+    pub path: String,
+    pub plugin: QwakPlugin,
+    /// How many calls to the plugin have failed.
+    pub faults: AtomicU32,
+    /// Disabled plugins are not called anymore.
+    pub disabled: AtomicBool,
+    /// Whether the plugin wants `map_tick` to be called.
+    pub ticks: bool,
+}
+impl LoadedQwak {
+    fn new(name: &str, path: String, plugin: QwakPlugin) -> Self {
+        let ticks = plugin.manifest().capabilities.iter().any(|c| c == "tick")
+            && plugin.exports("map_tick");
+        Self {
+            name: name.to_string(),
+            path,
+            ticks,
+            plugin,
+            faults: AtomicU32::new(0),
+            disabled: AtomicBool::new(false),
+        }
+    }
+}
+
+/// The loaded qwak plugins, the game module's plugin first followed by the mutators.
+/// Events are sent to every plugin in this order.
+#[derive(Debug, Resource)]
+pub struct Qwaks {
+    pub plugins: Vec<LoadedQwak>,
+    limits: QwakLimits,
+    /// Disable a plugin after this many failed calls.
+    disable_after: Option<u32>,
+    /// Failures which have not been shown to the players yet.
+    faults: Mutex<Vec<String>>,
+}
+impl Qwaks {
+    pub fn new<F: IntoIterator<Item = qwak::Function>>(
+        functions: impl Fn() -> F,
+        mutators: &[String],
+        disable_after: Option<u32>,
+    ) -> Self {
+        info!("Loading qwaks...");
+        let limits = QwakLimits::default();
+        let load =
+            |name: &str, path: String, loaded: &[LoadedQwak]| -> Result<LoadedQwak, String> {
+                let wasm = vfs::read(&path).map_err(|e| format!("failed reading {path:?}: {e}"))?;
+                let plugin = QwakPlugin::from_data(wasm, functions(), &limits)?;
+                check_dependencies(plugin.manifest(), loaded)?;
+                plugin.plugin_init().map_err(|e| format!("{e}"))?;
+                Ok(LoadedQwak::new(name, path, plugin))
+            };
+
+        let mut plugins = match load("default", QWAK_FILE.to_string(), &[]) {
+            Ok(o) => vec![o],
+            Err(e) => panic!("failed loading default qwak: {e}"),
+        };
+        for name in mutators {
+            match load(name, format!("{MUTATOR_DIR}/{name}.wasm"), &plugins) {
+                Ok(o) => plugins.push(o),
+                Err(e) => error!("failed loading mutator \"{name}\": {e}"),
+            }
+        }
+
+        info!("Done loading qwaks...");
+        Self {
+            plugins,
+            limits,
+            disable_after,
+            faults: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Loads the plugin at `index` again, and hands over the state of the old one.
+    /// ## Errors
+    /// Returns an error if the new plugin can't be loaded, the old one is kept in that case.
+    #[cfg(not(feature = "production"))]
+    pub fn reload<F: IntoIterator<Item = qwak::Function>>(
+        &mut self,
+        index: usize,
+        functions: impl Fn() -> F,
+    ) -> Result<(), String> {
+        let qwak = &self.plugins[index];
+        let wasm =
+            vfs::read(&qwak.path).map_err(|e| format!("failed reading {:?}: {e}", qwak.path))?;
+        let plugin = QwakPlugin::from_data(wasm, functions(), &self.limits)?;
+        check_dependencies(plugin.manifest(), &self.plugins[..index])?;
+        let state = qwak
+            .plugin
+            .plugin_save_state()
+            .map_err(|e| format!("{e}"))?;
+        plugin.plugin_init().map_err(|e| format!("{e}"))?;
+        plugin
+            .plugin_restore_state(state)
+            .map_err(|e| format!("{e}"))?;
+        let qwak = LoadedQwak::new(&qwak.name, qwak.path.clone(), plugin);
+        self.plugins[index] = qwak;
+        Ok(())
+    }
+
+    /// The game module's plugin, which names the game and decides how players are set up.
+    pub fn base(&self) -> &QwakPlugin {
+        &self.plugins[0].plugin
+    }
+
+    /// The names of the loaded mutators, in load order.
+    pub fn mutators(&self) -> Vec<String> {
+        self.plugins[1..].iter().map(|p| p.name.clone()).collect()
+    }
+
+    /// Returns the failures since the last call, to show to the players.
+    pub fn take_faults(&self) -> Vec<String> {
+        match self.faults.lock() {
+            Ok(mut o) => std::mem::take(&mut *o),
+            Err(e) => std::mem::take(&mut *e.into_inner()),
+        }
+    }
+
+    /// Logs a failed call, and disables the plugin if it has failed too many times.
+    fn fault(&self, qwak: &LoadedQwak, event: &str, error: impl std::fmt::Display) {
+        error!("qwak \"{}\" failed running `{event}`: {error}", qwak.name);
+        let mut faults = vec![format!("qwak \"{}\" failed running {event}", qwak.name)];
+        let count = qwak.faults.fetch_add(1, Ordering::Relaxed) + 1;
+        if self.disable_after.is_some_and(|max| count >= max) {
+            qwak.disabled.store(true, Ordering::Relaxed);
+            error!("qwak \"{}\" disabled after {count} failures", qwak.name);
+            faults.push(format!("qwak \"{}\" was disabled", qwak.name));
+        }
+        match self.faults.lock() {
+            Ok(mut o) => o.append(&mut faults),
+            Err(e) => e.into_inner().append(&mut faults),
+        }
+    }
+
+    /// Calls `f` on every enabled plugin in order, a failing plugin does not stop the others.
+    fn dispatch<E: std::fmt::Display>(
+        &self,
+        event: &str,
+        f: impl FnMut(&QwakPlugin) -> Result<(), E>,
+    ) {
+        self.dispatch_to(self.plugins.iter(), event, f);
+    }
+
+    /// Like [Qwaks::dispatch], but only to `plugins`.
+    fn dispatch_to<'a, E: std::fmt::Display>(
+        &self,
+        plugins: impl Iterator<Item = &'a LoadedQwak>,
+        event: &str,
+        mut f: impl FnMut(&QwakPlugin) -> Result<(), E>,
+    ) {
+        for qwak in plugins {
+            if qwak.disabled.load(Ordering::Relaxed) {
+                continue;
+            }
+            if let Err(e) = f(&qwak.plugin) {
+                self.fault(qwak, event, e);
+            }
+        }
+    }
+
+    /// Merges a map from every plugin, entries of later plugins replace the earlier ones.
+    fn merge<T, E: std::fmt::Display>(
+        &self,
+        what: &str,
+        mut f: impl FnMut(&QwakPlugin) -> Result<HashMap<FastStr, T>, E>,
+    ) -> HashMap<FastStr, T> {
+        let mut res = HashMap::new();
+        self.dispatch(what, |plugin| {
+            for (key, value) in f(plugin)? {
+                if res.insert(key.clone(), value).is_some() {
+                    warn!("{what} \"{key}\" was replaced by a later qwak");
+                }
+            }
+            Ok(())
+        });
+        res
+    }
+
+    pub fn plugin_name(&self) -> String {
+        self.base().manifest().name.clone()
+    }
+    pub fn plugin_version(&self) -> [i32; 3] {
+        self.base().manifest().version
+    }
+    pub fn player_info(&self) -> PlayerSpawnInfo {
+        self.base().player_info().unwrap_or_else(|e| {
+            self.fault(&self.plugins[0], "player_info", e);
+            PlayerSpawnInfo::default()
+        })
+    }
+
+    pub fn map_init(&self) {
+        self.dispatch("map_init", |p| p.map_init());
+    }
+    pub fn map_interact(&self, args: MapInteraction) {
+        self.dispatch("map_interact", |p| p.map_interact(args.clone()));
+    }
+    pub fn map_player_killed(&self, args: PlayerKilled) {
+        self.dispatch("map_player_killed", |p| p.map_player_killed(args));
+    }
+    pub fn map_player_respawn(&self, args: PlayerKilled) {
+        self.dispatch("map_player_respawn", |p| p.map_player_respawn(args));
+    }
+    pub fn map_player_join(&self, id: u64) {
+        self.dispatch("map_player_join", |p| p.map_player_join(id));
+    }
+    pub fn map_player_leave(&self, args: PlayerLeave) {
+        self.dispatch("map_player_leave", |p| p.map_player_leave(args.clone()));
+    }
+    pub fn map_menu_select(&self, args: MenuSelection) {
+        self.dispatch("map_menu_select", |p| p.map_menu_select(args.clone()));
+    }
+    pub fn plugin_command(&self, args: CommandCall) {
+        self.dispatch("plugin_command", |p| p.plugin_command(args.clone()));
+    }
+    /// Only the plugins which want ticks are called, the others cost nothing.
+    pub fn map_tick(&self, dt: f32) {
+        let plugins = self.plugins.iter().filter(|q| q.ticks);
+        self.dispatch_to(plugins, "map_tick", |p| p.map_tick(dt));
+    }
+    /// The lobby info of every plugin which has any, one per line.
+    pub fn map_get_lobby_info(&self) -> String {
+        let mut lines = Vec::new();
+        self.dispatch("map_get_lobby_info", |p| {
+            lines.push(p.map_get_lobby_info()?);
+            Ok::<_, qwak::Error>(())
+        });
+        lines.retain(|l| !l.is_empty());
+        lines.join("\n")
+    }
+
+    pub fn plugin_get_projectiles(&self) -> HashMap<FastStr, qwak_helper_types::Projectile> {
+        self.merge("projectile", |p| p.plugin_get_projectiles())
+    }
+    pub fn plugin_get_pickups(&self) -> HashMap<FastStr, qwak_helper_types::PickupData> {
+        self.merge("pickup", |p| p.plugin_get_pickups())
+    }
+    pub fn plugin_get_weapons(&self) -> HashMap<FastStr, qwak_helper_types::WeaponData> {
+        self.merge("weapon", |p| p.plugin_get_weapons())
+    }
+}
+
+/// Calls `map_tick` on the plugins at a fixed rate.
+#[derive(Debug, Resource)]
+pub struct QwakTicker {
+    timer: Timer,
+}
+impl QwakTicker {
+    /// The default amount of ticks per second.
+    const RATE: f32 = 20.0;
+    /// The most ticks run in one frame, the rest are dropped after a long stall.
+    const MAX_CATCH_UP: u32 = 4;
+
+    /// Ticks `rate` times per second, [QwakTicker::RATE] if [None].
+    pub fn new(rate: Option<f32>) -> Self {
+        let rate = rate.filter(|r| *r > 0.0).unwrap_or(Self::RATE);
+        Self {
+            timer: Timer::from_seconds(1.0 / rate, TimerMode::Repeating),
+        }
+    }
+
+    pub fn tick(mut ticker: ResMut<QwakTicker>, time: Res<Time>, qwaks: Res<Qwaks>) {
+        ticker.timer.tick(time.delta());
+        // Catch up on the ticks missed during a slow frame
+        let dt = ticker.timer.duration().as_secs_f32();
+        let ticks = ticker.timer.times_finished_this_tick();
+        if ticks > Self::MAX_CATCH_UP {
+            warn!("dropping {} plugin ticks", ticks - Self::MAX_CATCH_UP);
+        }
+        for _ in 0..ticks.min(Self::MAX_CATCH_UP) {
+            qwaks.map_tick(dt);
+        }
+    }
+}
+
+/// Checks that the plugins `manifest` depends on are loaded, in a new enough version.
+fn check_dependencies(manifest: &QwakManifest, loaded: &[LoadedQwak]) -> Result<(), String> {
+    let version = |v: [i32; 3]| format!("{}.{}.{}", v[0], v[1], v[2]);
+    for dep in &manifest.dependencies {
+        let Some(found) = loaded
+            .iter()
+            .map(|q| q.plugin.manifest())
+            .find(|m| m.name == dep.name)
+        else {
+            return Err(format!(
+                "\"{}\" depends on \"{}\", which is not loaded before it",
+                manifest.name, dep.name
+            ));
+        };
+        if found.version < dep.version {
+            return Err(format!(
+                "\"{}\" depends on \"{}\" {} or later, but {} is loaded",
+                manifest.name,
+                dep.name,
+                version(dep.version),
+                version(found.version)
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Developer tool which loads the plugins again when they change on disk.
+#[cfg(not(feature = "production"))]
+#[derive(Debug, Resource)]
+pub struct QwakWatcher {
+    timer: Timer,
+    modified: HashMap<String, std::time::SystemTime>,
+}
+#[cfg(not(feature = "production"))]
+impl Default for QwakWatcher {
+    fn default() -> Self {
+        Self {
+            timer: Timer::from_seconds(1.0, TimerMode::Repeating),
+            modified: HashMap::new(),
+        }
+    }
+}
+#[cfg(not(feature = "production"))]
+impl QwakWatcher {
+    pub fn watch(
+        mut watcher: ResMut<QwakWatcher>,
+        time: Res<Time>,
+        mut qwaks: ResMut<Qwaks>,
+        context: Res<crate::qwak_host_functions::QwakContext>,
+    ) {
+        if !watcher.timer.tick(time.delta()).just_finished() {
+            return;
+        }
+
+        for index in 0..qwaks.plugins.len() {
+            let path = qwaks.plugins[index].path.clone();
+            let Some(modified) = vfs::get()
+                .disk_path(&path)
+                .and_then(|p| std::fs::metadata(p).and_then(|m| m.modified()).ok())
+            else {
+                continue;
+            };
+            // Plugins seen for the first time are never counted as modified
+            if !watcher
+                .modified
+                .insert(path, modified)
+                .is_some_and(|old| old != modified)
+            {
+                continue;
+            }
+
+            let name = qwaks.plugins[index].name.clone();
+            info!("qwak \"{name}\" changed on disk, reloading...");
+            match qwaks.reload(index, || {
+                crate::qwak_host_functions::qwak_functions(&context)
+            }) {
+                Ok(()) => info!("reloaded qwak \"{name}\""),
+                Err(e) => error!("failed reloading qwak \"{name}\": {e}"),
+            }
+        }
+    }
+}
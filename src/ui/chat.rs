@@ -0,0 +1,94 @@
+use crate::{map_gen::GameObject, net::ClientMessage};
+use bevy::prelude::*;
+use bevy_simple_text_input::{
+    TextInput, TextInputInactive, TextInputSubmitEvent, TextInputTextFont, TextInputValue,
+};
+use resources::inputs::PlayerInput;
+
+/// The line the player types chat messages and commands in.
+#[derive(Debug, Component)]
+pub struct ChatLine;
+
+/// Opens the chat, or the console which is the chat starting with a `/`.
+pub fn open(
+    mut commands: Commands,
+    keys: Res<PlayerInput>,
+    lines: Query<(), With<ChatLine>>,
+    asset_server: Res<AssetServer>,
+) {
+    if !lines.is_empty() {
+        return;
+    }
+    let text = if keys.chat_just_pressed {
+        ""
+    } else if keys.console_just_pressed {
+        "/"
+    } else {
+        return;
+    };
+    commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            left: Val::Px(10.0),
+            bottom: Val::Px(60.0),
+            ..default()
+        },
+        TextInput,
+        TextInputValue(text.to_string()),
+        TextInputTextFont(TextFont {
+            font: asset_server.load("ui/Color Basic.otf"),
+            font_size: 24.0,
+            ..default()
+        }),
+        // Activated once the key opening it is released, so it isn't typed
+        TextInputInactive(true),
+        ChatLine,
+        Name::new("chat line"),
+        GameObject,
+    ));
+}
+
+/// Starts taking input once the chat has opened, and closes it with the pause key.
+pub fn update(
+    mut commands: Commands,
+    keys: Res<PlayerInput>,
+    mut lines: Query<(Entity, &mut TextInputInactive), With<ChatLine>>,
+) {
+    for (ent, mut inactive) in &mut lines {
+        if keys.pause_game_just_pressed || keys.pause_game_alt_just_pressed {
+            commands.entity(ent).despawn_recursive();
+        } else if inactive.0 && !keys.chat_pressed && !keys.console_pressed {
+            inactive.0 = false;
+        }
+    }
+}
+
+/// Sends the line and closes the chat, lines starting with a `/` are sent as commands.
+pub fn submit(
+    mut commands: Commands,
+    mut submits: EventReader<TextInputSubmitEvent>,
+    lines: Query<(), With<ChatLine>>,
+    mut client_events: EventWriter<ClientMessage>,
+) {
+    for submit in submits.read() {
+        if !lines.contains(submit.entity) {
+            continue;
+        }
+        commands.entity(submit.entity).despawn_recursive();
+        let line = submit.value.trim();
+        if let Some(line) = line.strip_prefix('/') {
+            client_events.send(ClientMessage::Command {
+                line: line.to_string(),
+            });
+        } else if !line.is_empty() {
+            client_events.send(ClientMessage::Chat {
+                text: line.to_string(),
+            });
+        }
+    }
+}
+
+/// Returns true if the chat is closed, the player is not controlled while typing.
+pub fn not_typing(lines: Query<(), With<ChatLine>>) -> bool {
+    lines.is_empty()
+}
@@ -0,0 +1,174 @@
+use crate::map_gen::GameObject;
+use bevy::prelude::*;
+use faststr::FastStr;
+use macros::error_continue;
+use qwak_helper_types::{HudAnchor, HudKind, HudWidget};
+
+/// A HUD widget created by a plugin.
+#[derive(Debug, Component)]
+pub struct HudElement {
+    pub name: FastStr,
+    pub widget: HudWidget,
+    /// Whether the widget is shown to every player.
+    pub global: bool,
+}
+impl HudElement {
+    /// The widget as it is shown right now, with the time left on a timer.
+    pub fn current_widget(&self, timer: Option<&HudTimer>) -> HudWidget {
+        let mut widget = self.widget.clone();
+        if let (HudKind::Timer { seconds }, Some(timer)) = (&mut widget.kind, timer) {
+            *seconds = timer.0.max(0.0);
+        }
+        widget
+    }
+}
+
+/// Counts down the text of a [HudKind::Timer], next to its [HudElement].
+#[derive(Debug, Component)]
+pub struct HudTimer(f32);
+
+/// Creates, replaces or removes (if `widget` is `None`) the widget called `name`.
+pub fn set(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    elements: &Query<(Entity, &HudElement)>,
+    name: FastStr,
+    widget: Option<HudWidget>,
+    global: bool,
+) {
+    for (ent, element) in elements {
+        if element.name == name {
+            commands.entity(ent).despawn_recursive();
+        }
+    }
+    let Some(widget) = widget else {
+        return;
+    };
+
+    let (justify_content, align_items) = match widget.anchor {
+        HudAnchor::TopLeft => (JustifyContent::FlexStart, AlignItems::FlexStart),
+        HudAnchor::Top => (JustifyContent::Center, AlignItems::FlexStart),
+        HudAnchor::TopRight => (JustifyContent::FlexEnd, AlignItems::FlexStart),
+        HudAnchor::Left => (JustifyContent::FlexStart, AlignItems::Center),
+        HudAnchor::Center => (JustifyContent::Center, AlignItems::Center),
+        HudAnchor::Right => (JustifyContent::FlexEnd, AlignItems::Center),
+        HudAnchor::BottomLeft => (JustifyContent::FlexStart, AlignItems::FlexEnd),
+        HudAnchor::Bottom => (JustifyContent::Center, AlignItems::FlexEnd),
+        HudAnchor::BottomRight => (JustifyContent::FlexEnd, AlignItems::FlexEnd),
+    };
+    let style = &widget.style;
+    let [r, g, b, a] = style.color;
+    let color = Color::srgba(r, g, b, a);
+    let content = Node {
+        left: Val::Px(style.offset[0]),
+        top: Val::Px(style.offset[1]),
+        ..default()
+    };
+    let font = TextFont {
+        font: asset_server.load("ui/Color Basic.otf"),
+        font_size: style.size,
+        ..default()
+    };
+
+    let mut element = commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            justify_content,
+            align_items,
+            padding: UiRect::all(Val::Px(10.0)),
+            ..default()
+        },
+        // Keeps widgets below the pause screen
+        GlobalZIndex(-1),
+        Name::new(format!("hud {name}")),
+        GameObject,
+    ));
+    element.with_children(|c| match &widget.kind {
+        HudKind::Text(text) => {
+            c.spawn((content, Text::new(text), font, TextColor(color)));
+        }
+        HudKind::Progress { value, max } => {
+            let filled = if *max > 0.0 {
+                (value / max).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            c.spawn((
+                Node {
+                    width: Val::Px(style.size * 10.0),
+                    height: Val::Px(style.size),
+                    border: UiRect::all(Val::Px(2.0)),
+                    ..content
+                },
+                BorderColor(color),
+            ))
+            .with_children(|c| {
+                c.spawn((
+                    Node {
+                        width: Val::Percent(filled * 100.0),
+                        height: Val::Percent(100.0),
+                        ..default()
+                    },
+                    BackgroundColor(color),
+                ));
+            });
+        }
+        HudKind::Icon(path) => {
+            c.spawn((
+                Node {
+                    width: Val::Px(style.size),
+                    height: Val::Px(style.size),
+                    ..content
+                },
+                ImageNode {
+                    image: asset_server.load(path),
+                    color,
+                    ..default()
+                },
+            ));
+        }
+        HudKind::Timer { seconds } => {
+            c.spawn((
+                content,
+                Text::new(timer_text(*seconds)),
+                font,
+                TextColor(color),
+            ));
+        }
+    });
+    if let HudKind::Timer { seconds } = &widget.kind {
+        element.insert(HudTimer(*seconds));
+    }
+    element.insert(HudElement {
+        name,
+        widget,
+        global,
+    });
+}
+
+fn timer_text(seconds: f32) -> String {
+    let seconds = seconds.max(0.0).ceil() as u32;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+pub fn update_timers(
+    mut timers: Query<(&mut HudTimer, &Children)>,
+    mut texts: Query<&mut Text>,
+    time: Res<Time>,
+) {
+    for (mut timer, children) in &mut timers {
+        if timer.0 <= 0.0 {
+            continue;
+        }
+        timer.0 -= time.delta_secs();
+        let new = timer_text(timer.0);
+        for child in children {
+            let mut text = error_continue!(texts.get_mut(*child));
+            if text.0 != new {
+                text.0 = new.clone();
+            }
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use crate::net::map_transfer::MapDownload;
+use bevy::prelude::*;
+use resources::{MapLoadingProgress, MapLoadingState};
+
+/// Marks everything belonging to the loading screen.
+#[derive(Debug, Component)]
+pub struct LoadingScreenEnt;
+
+#[derive(Debug, Component)]
+pub struct LoadingScreenText;
+
+#[derive(Debug, Component)]
+pub struct LoadingScreenBar;
+
+pub fn spawn(mut commands: Commands, query: Query<(), With<LoadingScreenEnt>>) {
+    if !query.is_empty() {
+        return;
+    }
+
+    let camera = commands
+        .spawn((
+            Camera2d,
+            Camera {
+                order: 3,
+                clear_color: ClearColorConfig::Custom(Color::BLACK),
+                ..default()
+            },
+            Msaa::Off,
+            LoadingScreenEnt,
+        ))
+        .id();
+
+    commands
+        .spawn((
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                row_gap: Val::Px(10.0),
+                ..default()
+            },
+            TargetCamera(camera),
+            LoadingScreenEnt,
+        ))
+        .with_children(|c| {
+            c.spawn((
+                Text::new(""),
+                TextFont {
+                    font_size: 32.0,
+                    ..default()
+                },
+                LoadingScreenText,
+            ));
+            c.spawn((
+                Node {
+                    width: Val::Px(400.0),
+                    height: Val::Px(16.0),
+                    border: UiRect::all(Val::Px(2.0)),
+                    ..default()
+                },
+                BorderColor(Color::WHITE),
+            ))
+            .with_children(|c| {
+                c.spawn((
+                    Node {
+                        width: Val::Percent(0.0),
+                        height: Val::Percent(100.0),
+                        ..default()
+                    },
+                    BackgroundColor(Color::WHITE),
+                    LoadingScreenBar,
+                ));
+            });
+        });
+}
+
+pub fn update(
+    state: Res<MapLoadingState>,
+    progress: Res<MapLoadingProgress>,
+    download: Option<Res<MapDownload>>,
+    mut text: Query<&mut Text, With<LoadingScreenText>>,
+    mut bar: Query<&mut Node, With<LoadingScreenBar>>,
+) {
+    for mut text in &mut text {
+        text.0 = if download.is_some() {
+            format!("downloading map... {}/{}", progress.done, progress.total)
+        } else {
+            format!("{state}... {}/{}", progress.done, progress.total)
+        };
+    }
+    for mut node in &mut bar {
+        node.width = Val::Percent(progress.fraction() * 100.0);
+    }
+}
+
+pub fn despawn(mut commands: Commands, query: Query<Entity, With<LoadingScreenEnt>>) {
+    for ent in &query {
+        commands.entity(ent).despawn_recursive();
+    }
+}
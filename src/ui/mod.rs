@@ -1,6 +1,10 @@
 use bevy::{ecs::schedule::SystemConfigs, prelude::IntoSystemConfigs};
 
+pub mod chat;
+pub mod hud;
+pub mod loading_screen;
 pub mod menu_button;
+pub mod plugin_menu;
 
 pub fn ui_systems() -> SystemConfigs {
     (menu_button::update,).into_configs()
@@ -0,0 +1,143 @@
+use super::menu_button::MenuButton;
+use crate::{
+    map_gen::GameObject,
+    net::ClientMessage,
+    player::{Player, PlayerController},
+};
+use bevy::{
+    prelude::*,
+    window::{CursorGrabMode, PrimaryWindow},
+};
+use faststr::FastStr;
+use qwak_helper_types::Menu;
+use resources::Paused;
+
+/// A modal menu opened by a plugin.
+#[derive(Debug, Component)]
+pub struct PluginMenu {
+    pub name: FastStr,
+}
+
+/// A button in a [PluginMenu].
+#[derive(Debug, Component, Default)]
+pub struct PluginMenuChoice {
+    menu: FastStr,
+    choice: FastStr,
+}
+
+/// Opens, replaces or closes (if `menu` is `None`) the menu called `name`.
+pub fn set(
+    commands: &mut Commands,
+    menus: &Query<(Entity, &PluginMenu)>,
+    name: FastStr,
+    menu: Option<Menu>,
+) {
+    close(commands, menus, &name);
+    let Some(menu) = menu else {
+        return;
+    };
+
+    commands
+        .spawn((
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.5)),
+            Name::new(format!("menu {name}")),
+            GameObject,
+        ))
+        .with_children(|c| {
+            c.spawn((
+                Node {
+                    width: Val::Px(230.0),
+                    align_items: AlignItems::Stretch,
+                    flex_direction: FlexDirection::Column,
+                    row_gap: Val::Px(10.0),
+                    padding: UiRect::all(Val::Px(10.0)),
+                    ..default()
+                },
+                BackgroundColor(Color::BLACK),
+            ))
+            .with_children(|c| {
+                c.spawn((
+                    Text::new(menu.title),
+                    TextFont {
+                        font_size: 32.0,
+                        ..default()
+                    },
+                ));
+                for choice in menu.choices {
+                    c.spawn(MenuButton::new(
+                        choice.text,
+                        None,
+                        None,
+                        None,
+                        PluginMenuChoice {
+                            menu: name.clone(),
+                            choice: choice.id.into(),
+                        },
+                    ));
+                }
+            });
+        })
+        .insert(PluginMenu { name });
+}
+
+fn close(commands: &mut Commands, menus: &Query<(Entity, &PluginMenu)>, name: &str) {
+    for (ent, menu) in menus {
+        if menu.name == name {
+            commands.entity(ent).despawn_recursive();
+        }
+    }
+}
+
+/// Sends the pressed choice to the server and closes the menu.
+#[allow(clippy::type_complexity)]
+pub fn buttons(
+    mut commands: Commands,
+    interactions: Query<(&Interaction, &PluginMenuChoice), (Changed<Interaction>, With<Button>)>,
+    menus: Query<(Entity, &PluginMenu)>,
+    mut client_events: EventWriter<ClientMessage>,
+) {
+    for (interaction, choice) in &interactions {
+        if matches!(interaction, Interaction::Pressed) {
+            close(&mut commands, &menus, &choice.menu);
+            client_events.send(ClientMessage::MenuSelect {
+                menu: choice.menu.clone(),
+                choice: choice.choice.clone(),
+            });
+        }
+    }
+}
+
+/// Frees the cursor while a menu is open, like the pause screen does.
+pub fn grab_cursor(
+    added: Query<(), Added<PluginMenu>>,
+    mut removed: RemovedComponents<PluginMenu>,
+    menus: Query<(), With<PluginMenu>>,
+    paused: Res<Paused>,
+    players: Query<&Player, With<PlayerController>>,
+    mut q_windows: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    let closed = removed.read().count() > 0;
+    let unpaused = paused.is_changed() && !paused.0;
+    let mut window = q_windows.single_mut();
+    if !added.is_empty() || (unpaused && !menus.is_empty()) {
+        window.cursor_options.grab_mode = CursorGrabMode::None;
+        window.cursor_options.visible = true;
+        let middle = window.size() / 2.0;
+        window.set_cursor_position(Some(middle));
+    } else if closed && menus.is_empty() && !paused.0 && players.iter().all(|p| !p.dead) {
+        window.cursor_options.grab_mode = CursorGrabMode::Locked;
+        window.cursor_options.visible = false;
+    }
+}
+
+/// Returns true if no plugin menu is open, the player is not controlled while one is.
+pub fn no_menu_open(menus: Query<(), With<PluginMenu>>) -> bool {
+    menus.is_empty()
+}